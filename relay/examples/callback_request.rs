@@ -14,9 +14,11 @@
 
 use anyhow::Context;
 use bonsai_ethereum_relay::sdk::client::{CallbackRequest, Client};
+use bonsai_ethereum_relay_cli::stm_verification_input;
+use bonsai_experiment::{deserialize_avk, GuestInput};
 use clap::Parser;
-use ethers::{abi::ethabi, types::Address};
-use methods::VERIFICATION_ID;
+use ethers::{types::Address, utils::id};
+use methods::STM_VERIFICATION_ID;
 use risc0_zkvm::sha::Digest;
 
 /// Exmaple code for sending a REST API request to the Bonsai relay service to
@@ -28,8 +30,14 @@ struct Args {
     /// Adress for the BonsaiStarter application contract.
     address: Address,
 
-    /// Input N for calculating the Nth Fibonacci number.
-    number: u64,
+    /// Hex of the signed message.
+    message: String,
+
+    /// Hex of the serialized certificate over the message.
+    certificate: String,
+
+    /// Hex of the serialized aggregate verification key of the committee.
+    avk: String,
 
     /// Bonsai Relay API URL.
     #[arg(long, env, default_value = "http://localhost:8080")]
@@ -51,18 +59,24 @@ async fn main() -> anyhow::Result<()> {
     )
     .context("Failed to initialize the relay client")?;
 
-    // Initialize the input for the FIBONACCI guest.
-    let input = ethabi::encode(&[ethers::abi::Token::Uint(args.number.into())]);
+    // Initialize the input for the STM verification guest.
+    let avk = hex::decode(args.avk.trim_start_matches("0x")).context("Invalid AVK hex")?;
+    let input = stm_verification_input(&GuestInput {
+        msg: hex::decode(args.message.trim_start_matches("0x")).context("Invalid message hex")?,
+        certificate: hex::decode(args.certificate.trim_start_matches("0x"))
+            .context("Invalid certificate hex")?,
+        avk: deserialize_avk(&avk).context("Invalid AVK")?,
+    })?;
 
     // Create a CallbackRequest for your contract
     // example: (contracts/BonsaiStarter.sol).
     let request = CallbackRequest {
         callback_contract: args.address,
         // you can use the command `solc --hashes contracts/BonsaiStarter.sol`
-        // to get the value for your actual contract (9f2275c0: storeResult(uint256,uint256))
-        function_selector: [0x9f, 0x22, 0x75, 0xc0],
+        // to list the selectors of your actual contract
+        function_selector: id("storeResult((bytes,bytes),bool)"),
         gas_limit: 3000000,
-        image_id: Digest::from(VERIFICATION_ID).into(),
+        image_id: Digest::from(STM_VERIFICATION_ID).into(),
         input,
     };
 
@@ -15,10 +15,13 @@
 use std::time::Duration;
 
 use anyhow::{anyhow, bail, Context, Result};
+use bonsai_experiment::{GuestInput, GuestJournal};
 use bonsai_sdk::alpha::{responses::SnarkProof, Client, SdkErr};
+use methods::STM_VERIFICATION_ELF;
 use risc0_build::GuestListEntry;
 use risc0_zkvm::{
-    Executor, ExecutorEnv, MemoryImage, Program, Receipt, ReceiptMetadata, MEM_SIZE, PAGE_SIZE,
+    default_prover, Executor, ExecutorEnv, MemoryImage, Program, Receipt, ReceiptMetadata,
+    MEM_SIZE, PAGE_SIZE,
 };
 
 /// Result of executing a guest image, possibly containing a proof.
@@ -192,3 +195,40 @@ pub async fn resolve_image_output(
             .context("Failed to run alpha sub-task")?
     }
 }
+
+/// Encode a message, its serialized certificate and the aggregate verification
+/// key of the signing committee as the input of the STM verification guest.
+pub fn stm_verification_input(input: &GuestInput) -> Result<Vec<u8>> {
+    let words = risc0_zkvm::serde::to_vec(input).context("Failed to encode guest input")?;
+    Ok(bytemuck::cast_slice(&words).to_vec())
+}
+
+/// Decode the journal committed by the STM verification guest.
+pub fn decode_stm_verification_journal(journal: &[u8]) -> Result<GuestJournal> {
+    risc0_zkvm::serde::from_slice(journal).context("Failed to decode guest journal")
+}
+
+/// Prove that the STM verification guest checked the certificate of `input`,
+/// returning the receipt whose journal holds the [GuestJournal].
+///
+/// The prover is picked by [default_prover], so `RISC0_PROVER`,
+/// `RISC0_DEV_MODE` and the Bonsai environment variables apply.
+pub fn prove_verification(input: &GuestInput) -> Result<Receipt> {
+    let input = stm_verification_input(input)?;
+    let env = ExecutorEnv::builder()
+        .add_input(&input)
+        .build()
+        .context("Failed to build exec env")?;
+    default_prover()
+        .prove_elf(env, STM_VERIFICATION_ELF)
+        .context("Failed to prove STM verification")
+}
+
+/// Check that `receipt` was produced by the guest with `image_id`, usually
+/// `methods::STM_VERIFICATION_ID`, and return the committed journal.
+pub fn verify_receipt(receipt: &Receipt, image_id: [u32; 8]) -> Result<GuestJournal> {
+    receipt
+        .verify(image_id)
+        .context("Failed to verify receipt")?;
+    decode_stm_verification_journal(&receipt.journal)
+}
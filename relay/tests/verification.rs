@@ -0,0 +1,69 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bonsai_ethereum_relay_cli::{
+    decode_stm_verification_journal, prove_verification, verify_receipt,
+};
+use bonsai_experiment::{
+    compute_avk_for_stake, default_params, generate_aggregate_signatures, message_digest,
+    serialize_avk, serialize_certificate, GuestInput, GuestJournal,
+};
+use methods::STM_VERIFICATION_ID;
+
+#[test]
+#[ignore = "proving takes minutes; run with RISC0_DEV_MODE=1 cargo test -- --ignored"]
+fn proved_verification_commits_the_verdict() {
+    let params = default_params();
+    let msg = b"prove verification".to_vec();
+    let msig = generate_aggregate_signatures(&msg, params, 4).unwrap();
+    let input = GuestInput {
+        msg: msg.clone(),
+        certificate: serialize_certificate(&msig),
+        avk: compute_avk_for_stake(vec![1; 4], params),
+    };
+
+    let receipt = prove_verification(&input).unwrap();
+    let journal = verify_receipt(&receipt, STM_VERIFICATION_ID).unwrap();
+    assert!(journal.verdict);
+    assert_eq!(journal.msg_digest, message_digest(&msg));
+    assert_eq!(
+        journal.avk_fingerprint,
+        message_digest(&serialize_avk(&input.avk))
+    );
+}
+
+#[test]
+fn journal_decodes_as_the_guest_committed_it() {
+    let params = default_params();
+    let msg = b"journal round trip".to_vec();
+    let msig = generate_aggregate_signatures(&msg, params, 4).unwrap();
+    let input = GuestInput {
+        msg,
+        certificate: serialize_certificate(&msig),
+        avk: compute_avk_for_stake(vec![1; 4], params),
+    };
+
+    for journal in [
+        input.verify(params),
+        GuestJournal {
+            verdict: false,
+            ..input.verify(params)
+        },
+    ] {
+        // `env::commit` writes the words of the risc0 serde encoding.
+        let words = risc0_zkvm::serde::to_vec(&journal).unwrap();
+        let bytes: &[u8] = bytemuck::cast_slice(&words);
+        assert_eq!(decode_stm_verification_journal(bytes).unwrap(), journal);
+    }
+}
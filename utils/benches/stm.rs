@@ -0,0 +1,138 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Aggregation and verification cost of STM certificates by committee size.
+//!
+//! Key generation dominates the setup of a committee and is kept out of the
+//! measured region, except for `setup`, which measures it, and the standalone
+//! path of `verify_repeated`, which rederives the committee from its stake
+//! distribution on every call.
+
+use bonsai_experiment::{
+    compute_avk_for_stake, default_params, find_signatures, generate_aggregate_signatures,
+    serialize_avk, serialize_certificate, setup_committee, setup_equal_parties, sign_with_mode,
+    try_compute_avk_for_stake, verify_aggregate_signature, verify_with_avk_bytes, MessageMode,
+    Verifier,
+};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use mithril_stm::stm::StmClerk;
+
+const PARTY_COUNTS: [usize; 4] = [4, 16, 64, 256];
+
+const MSG: [u8; 6] = [0, 1, 2, 3, 4, 5];
+
+fn aggregation(c: &mut Criterion) {
+    let params = default_params();
+    let mut group = c.benchmark_group("aggregate");
+    group.sample_size(10);
+
+    for nparties in PARTY_COUNTS {
+        let ps = setup_equal_parties(params, nparties);
+        let all_ps: Vec<usize> = (0..nparties).collect();
+
+        group.throughput(Throughput::Elements(nparties as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(nparties), &ps, |b, ps| {
+            b.iter_batched(
+                || StmClerk::from_signer(&ps[0]),
+                |clerk| {
+                    let sigs = find_signatures(&MSG, ps, &all_ps);
+                    clerk.aggregate(&sigs, &MSG)
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// The default parameters reach the quorum for every committee size measured.
+#[allow(clippy::unwrap_used)]
+fn verification(c: &mut Criterion) {
+    let params = default_params();
+    let mut group = c.benchmark_group("verify");
+    group.sample_size(10);
+
+    for nparties in PARTY_COUNTS {
+        let msig = generate_aggregate_signatures(&MSG, params, nparties).unwrap();
+        let avk = compute_avk_for_stake(vec![1; nparties], params);
+
+        group.throughput(Throughput::Elements(nparties as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(nparties), &msig, |b, msig| {
+            b.iter_batched(
+                || msig.clone(),
+                |msig| verify_aggregate_signature(&MSG, &msig, &avk, params),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// The default parameters reach the quorum for every committee size measured.
+#[allow(clippy::unwrap_used)]
+fn repeated_verification(c: &mut Criterion) {
+    let params = default_params();
+    let mut group = c.benchmark_group("verify_repeated");
+    group.sample_size(10);
+
+    for nparties in PARTY_COUNTS {
+        let stake = vec![1; nparties];
+        let (ps, reg) = setup_committee(params, stake.clone());
+        let msig = sign_with_mode(MessageMode::Raw, &MSG, &ps).unwrap();
+        let cert_bytes = serialize_certificate(&msig);
+        let verifier = Verifier::new(&reg, params).unwrap();
+
+        group.throughput(Throughput::Elements(nparties as u64));
+        group.bench_with_input(
+            BenchmarkId::new("standalone", nparties),
+            &cert_bytes,
+            |b, cert_bytes| {
+                b.iter(|| {
+                    let avk = try_compute_avk_for_stake(stake.clone(), params).unwrap();
+                    verify_with_avk_bytes(&MSG, cert_bytes, &serialize_avk(&avk), params)
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("verifier", nparties),
+            &cert_bytes,
+            |b, cert_bytes| b.iter(|| verifier.verify(&MSG, cert_bytes)),
+        );
+    }
+    group.finish();
+}
+
+// Building a signer copies the parties of the registration into it, which
+// adds up for the largest committee.
+fn setup(c: &mut Criterion) {
+    let params = default_params();
+    let mut group = c.benchmark_group("setup");
+    group.sample_size(10);
+
+    let nparties = PARTY_COUNTS[PARTY_COUNTS.len() - 1];
+    group.throughput(Throughput::Elements(nparties as u64));
+    group.bench_function(BenchmarkId::from_parameter(nparties), |b| {
+        b.iter(|| setup_committee(params, vec![1; nparties]))
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    aggregation,
+    verification,
+    repeated_verification,
+    setup
+);
+criterion_main!(benches);
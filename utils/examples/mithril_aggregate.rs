@@ -0,0 +1,74 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Write `fixtures/mithril_aggregate.hex`, an aggregate encoded by
+//! mithril-stm's own `StmAggrSig::to_bytes`.
+//!
+//! Only mithril-stm is used, following its `key_registration` example, so the
+//! fixture pins the upstream encoding rather than this crate's. The committee
+//! is the one `setup_committee` derives for four parties of stake 1 under the
+//! default parameters and seed, so tests can rebuild the same aggregate and
+//! compare the two encodings.
+//!
+//! ```text
+//! cargo run --example mithril_aggregate
+//! ```
+
+use std::{error::Error, fs, path::Path};
+
+use blake2::{digest::consts::U32, Blake2b};
+use mithril_stm::{
+    key_reg::KeyReg,
+    stm::{StmClerk, StmInitializer, StmParameters},
+};
+use rand_chacha::ChaCha20Rng;
+use rand_core::SeedableRng;
+
+type H = Blake2b<U32>;
+
+const MSG: &[u8] = b"certificate round trip";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let params = StmParameters {
+        k: 357,
+        m: 2642,
+        phi_f: 0.2,
+    };
+
+    // Every party draws its keys from its own stream of the all-zero seed.
+    let initializers: Vec<StmInitializer> = (0..4)
+        .map(|i| {
+            let mut rng = ChaCha20Rng::from_seed([0; 32]);
+            rng.set_stream(i);
+            StmInitializer::setup(params, 1, &mut rng)
+        })
+        .collect();
+    let mut key_reg = KeyReg::init();
+    for initializer in &initializers {
+        key_reg.register(initializer.stake, initializer.verification_key())?;
+    }
+    let closed_reg = key_reg.close::<H>();
+
+    let mut signers = Vec::new();
+    for initializer in initializers {
+        signers.push(initializer.new_signer(closed_reg.clone())?);
+    }
+    let sigs: Vec<_> = signers.iter().filter_map(|s| s.sign(MSG)).collect();
+    let clerk = StmClerk::from_signer(&signers[0]);
+    let msig = clerk.aggregate(&sigs, MSG)?;
+
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/mithril_aggregate.hex");
+    fs::write(path, format!("{}\n", hex::encode(msig.to_bytes())))?;
+    Ok(())
+}
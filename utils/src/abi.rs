@@ -0,0 +1,338 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Ethereum ABI representation of the data submitted for verification.
+
+use ethabi::{ethereum_types::H256, Bytes, ParamType, Token};
+use mithril_stm::{
+    key_reg::ClosedKeyReg,
+    stm::{StmAggrSig, StmAggrVerificationKey},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    deserialize_certificate, message_digest, serialize_avk, serialize_certificate,
+    CertificateError, D, H,
+};
+
+/// Data submitted to the `BonsaiStarter` contract for verification, as 32-byte
+/// digests of the message and of the certificate.
+///
+/// The digests cannot be reversed: neither the message nor the certificate can
+/// be recovered from them. Use [FullVerificationData] where the certificate
+/// itself must travel with the digests.
+///
+/// With serde, both digests are written as `0x`-prefixed hex strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VerificationData {
+    /// The signed message.
+    #[serde(with = "hex_h256")]
+    pub msg: H256,
+    /// Digest of the aggregate signature over `msg`, not the signature itself.
+    #[serde(with = "hex_h256")]
+    pub msig: H256,
+}
+
+impl VerificationData {
+    /// Digest `msg` and the serialized `msig`; both may be of any length, and
+    /// `msg` may hold any bytes, UTF-8 or not.
+    pub fn new(msg: &[u8], msig: &StmAggrSig<H>) -> Self {
+        Self::from_message_and_cert(msg, msig)
+    }
+
+    /// Digest `msg` and the [serialize_certificate] encoding of `msig` with the
+    /// [DefaultHasher](crate::DefaultHasher), so callers never build the
+    /// [H256] fields by hand.
+    pub fn from_message_and_cert(msg: &[u8], msig: &StmAggrSig<H>) -> Self {
+        Self {
+            msg: digest_to_h256(msg),
+            msig: digest_to_h256(&serialize_certificate(msig)),
+        }
+    }
+
+    /// Always `true`: the certificate cannot be recovered from
+    /// [VerificationData::msig].
+    pub fn is_digest_only(&self) -> bool {
+        true
+    }
+
+    /// ABI-encode as the Solidity `struct { bytes32 msg; bytes32 msig; }`,
+    /// i.e. two 32-byte words.
+    pub fn encode_abi(&self) -> Bytes {
+        ethabi::encode(&[Token::Tuple(vec![
+            Token::FixedBytes(self.msg.as_bytes().to_vec()),
+            Token::FixedBytes(self.msig.as_bytes().to_vec()),
+        ])])
+    }
+
+    /// Decode data produced by [VerificationData::encode_abi].
+    pub fn decode_abi(data: &[u8]) -> Result<Self, ethabi::Error> {
+        let ty = ParamType::Tuple(vec![ParamType::FixedBytes(32), ParamType::FixedBytes(32)]);
+        match ethabi::decode(&[ty], data)?.as_slice() {
+            [Token::Tuple(fields)] => match fields.as_slice() {
+                [Token::FixedBytes(msg), Token::FixedBytes(msig)] => Ok(Self {
+                    msg: H256::from_slice(msg),
+                    msig: H256::from_slice(msig),
+                }),
+                _ => Err(ethabi::Error::InvalidData),
+            },
+            _ => Err(ethabi::Error::InvalidData),
+        }
+    }
+}
+
+/// [VerificationData] together with the serialized certificate it digests, for
+/// when the certificate must be transported as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FullVerificationData {
+    data: VerificationData,
+    certificate: Vec<u8>,
+}
+
+impl FullVerificationData {
+    /// Digest `msg` and `msig` as [VerificationData::from_message_and_cert]
+    /// does, keeping the serialized `msig`.
+    pub fn new(msg: &[u8], msig: &StmAggrSig<H>) -> Self {
+        let certificate = serialize_certificate(msig);
+        Self {
+            data: VerificationData {
+                msg: digest_to_h256(msg),
+                msig: digest_to_h256(&certificate),
+            },
+            certificate,
+        }
+    }
+
+    /// Always `false`: the certificate is carried in full.
+    pub fn is_digest_only(&self) -> bool {
+        false
+    }
+
+    /// The digests submitted to the contract.
+    pub fn verification_data(&self) -> VerificationData {
+        self.data
+    }
+
+    /// The [serialize_certificate] encoding of the certificate.
+    pub fn certificate_bytes(&self) -> &[u8] {
+        &self.certificate
+    }
+
+    /// Decode the certificate.
+    pub fn certificate(&self) -> Result<StmAggrSig<H>, CertificateError> {
+        deserialize_certificate(&self.certificate)
+    }
+}
+
+/// Hash `data` with the [DefaultHasher](crate::DefaultHasher) into a single
+/// 32-byte word.
+pub fn digest_to_h256(data: &[u8]) -> H256 {
+    H256(message_digest(data))
+}
+
+/// Digest of the [serialize_avk] encoding of `avk`, which lets nodes check that
+/// they agree on a committee without exchanging its keys.
+///
+/// Any difference in the parties, keys or stakes of the committee changes the
+/// fingerprint.
+pub fn avk_fingerprint(avk: &StmAggrVerificationKey<D>) -> H256 {
+    digest_to_h256(&serialize_avk(avk))
+}
+
+/// Root of the Merkle tree over the registered parties of `reg`, the
+/// commitment carried by its aggregate verification key, e.g. for light
+/// clients checking the inclusion of a single signer.
+///
+/// The root is the zero word if `reg` holds no party. mithril-stm does not
+/// export the type of its membership paths, so no path is exposed alongside
+/// the root.
+pub fn committee_root(reg: &ClosedKeyReg<D>) -> H256 {
+    // mithril-stm keeps the nodes private, so they are recovered from the
+    // serde encoding of the tree, whose first field is the node list with the
+    // root first. Encoding an in-memory tree and decoding a prefix of it
+    // cannot fail.
+    let nodes = bincode::serialize(&*reg.merkle_tree)
+        .and_then(|bytes| bincode::deserialize::<(Vec<Vec<u8>>,)>(&bytes))
+        .map(|(nodes,)| nodes)
+        .unwrap_or_default();
+    match nodes.first() {
+        Some(root) if !reg.reg_parties.is_empty() && root.len() == 32 => H256::from_slice(root),
+        _ => H256::zero(),
+    }
+}
+
+/// Serde representation of an [H256] as a `0x`-prefixed hex string.
+mod hex_h256 {
+    use ethabi::ethereum_types::H256;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &H256, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(value.as_bytes())))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<H256, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let digits = s.strip_prefix("0x").unwrap_or(&s);
+        let bytes = hex::decode(digits).map_err(D::Error::custom)?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| D::Error::invalid_length(bytes.len(), &"32 bytes"))?;
+        Ok(H256(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compute_avk_for_stake, default_params, generate_aggregate_signatures, setup_committee,
+        Stake,
+    };
+
+    #[test]
+    fn verification_data_accepts_messages_of_any_length() {
+        let msig = generate_aggregate_signatures(b"abi tests", default_params(), 4).unwrap();
+        let cert_digest = digest_to_h256(&serialize_certificate(&msig));
+
+        for len in [0, 1, 6, 31, 32, 33, 4096] {
+            let msg: Vec<u8> = (0..len).map(|i| (i % 256) as u8 ^ 0x80).collect();
+            let data = VerificationData::new(&msg, &msig);
+            assert_eq!(data.msg, digest_to_h256(&msg));
+            assert_eq!(data.msig, cert_digest);
+        }
+    }
+
+    #[test]
+    fn abi_encoding_round_trips_as_two_words() {
+        let data = VerificationData {
+            msg: digest_to_h256(b"message"),
+            msig: digest_to_h256(b"certificate"),
+        };
+        let encoded = data.encode_abi();
+        assert_eq!(encoded.len(), 64);
+        assert_eq!(&encoded[..32], data.msg.as_bytes());
+        assert_eq!(&encoded[32..], data.msig.as_bytes());
+        assert_eq!(encoded, data.encode_abi());
+
+        let decoded = VerificationData::decode_abi(&encoded).unwrap();
+        assert_eq!(decoded.msg, data.msg);
+        assert_eq!(decoded.msig, data.msig);
+        assert!(VerificationData::decode_abi(&encoded[..63]).is_err());
+    }
+
+    #[test]
+    fn json_round_trips_as_hex_strings() {
+        let data = VerificationData {
+            msg: digest_to_h256(b"message"),
+            msig: digest_to_h256(b"certificate"),
+        };
+        let json = serde_json::to_value(data).unwrap();
+        assert_eq!(
+            json["msg"],
+            format!("0x{}", hex::encode(data.msg.as_bytes()))
+        );
+        assert_eq!(
+            serde_json::from_value::<VerificationData>(json).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn json_digests_of_the_wrong_length_are_rejected() {
+        let short =
+            serde_json::json!({ "msg": "0x0102", "msig": format!("0x{}", "00".repeat(32)) });
+        let err = serde_json::from_value::<VerificationData>(short).unwrap_err();
+        assert!(err.to_string().contains("32 bytes"));
+
+        let odd = serde_json::json!({ "msg": "0x012", "msig": "0x01" });
+        assert!(serde_json::from_value::<VerificationData>(odd).is_err());
+    }
+
+    #[test]
+    fn fingerprint_changes_with_the_stake() {
+        let params = default_params();
+        let fingerprint =
+            |stake: Vec<Stake>| avk_fingerprint(&compute_avk_for_stake(stake, params));
+
+        assert_eq!(fingerprint(vec![1; 4]), fingerprint(vec![1; 4]));
+        assert_ne!(fingerprint(vec![1; 4]), fingerprint(vec![1, 1, 2, 1]));
+    }
+
+    #[test]
+    fn data_from_message_and_cert_digests_both() {
+        let params = default_params();
+        let msg = b"abi tests";
+        let four = generate_aggregate_signatures(msg, params, 4).unwrap();
+        let five = generate_aggregate_signatures(msg, params, 5).unwrap();
+
+        let a = VerificationData::from_message_and_cert(msg, &four);
+        let b = VerificationData::from_message_and_cert(msg, &five);
+        assert_eq!(a.msg, b.msg);
+        assert_eq!(a.msg, digest_to_h256(msg));
+        assert_ne!(a.msig, b.msig);
+        assert_eq!(a.msig, digest_to_h256(&serialize_certificate(&four)));
+    }
+
+    #[test]
+    fn only_full_data_carries_the_certificate() {
+        let msg = b"abi tests";
+        let msig = generate_aggregate_signatures(msg, default_params(), 4).unwrap();
+        let data = VerificationData::from_message_and_cert(msg, &msig);
+        let full = FullVerificationData::new(msg, &msig);
+
+        assert!(data.is_digest_only());
+        assert!(!full.is_digest_only());
+        assert_eq!(full.verification_data(), data);
+        assert_eq!(full.certificate_bytes(), serialize_certificate(&msig));
+        let decoded = full.certificate().unwrap();
+        assert_eq!(
+            serialize_certificate(&decoded),
+            serialize_certificate(&msig)
+        );
+    }
+
+    #[test]
+    fn committee_root_is_stable_and_commits_to_every_key() {
+        let params = default_params();
+        let root = committee_root(&setup_committee(params, vec![1; 4]).1);
+        assert_ne!(root, H256::zero());
+        assert_eq!(committee_root(&setup_committee(params, vec![1; 4]).1), root);
+
+        // The same four keys with a fifth one added.
+        assert_ne!(committee_root(&setup_committee(params, vec![1; 5]).1), root);
+    }
+
+    #[test]
+    fn verification_data_compares_and_hashes_by_its_digests() {
+        use std::collections::HashSet;
+
+        let msig = generate_aggregate_signatures(b"abi tests", default_params(), 4).unwrap();
+        let data = VerificationData::new(b"abi tests", &msig);
+        let same = VerificationData::new(b"abi tests", &msig);
+        let other_msg = VerificationData::new(b"other message", &msig);
+        let other_cert = VerificationData {
+            msig: digest_to_h256(b"other certificate"),
+            ..data
+        };
+
+        assert_eq!(data, same);
+        assert_ne!(data, other_msg);
+        assert_ne!(data, other_cert);
+        let set: HashSet<VerificationData> = [data, same, other_msg, other_cert]
+            .into_iter()
+            .collect();
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(&data));
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Aggregation of signatures as they arrive, for producing a certificate as
+//! soon as a quorum is reached.
+
+use std::collections::BTreeSet;
+
+use mithril_stm::{
+    key_reg::ClosedKeyReg,
+    stm::{StmAggrSig, StmAggrVerificationKey, StmParameters, StmSig},
+};
+use tracing::debug;
+
+use crate::{
+    aggregate_external, try_compute_avk_for_reg, verify_single_sig, AggregationError, D, H,
+};
+
+/// Aggregator that is fed the signatures over one message one at a time, e.g.
+/// as they come in from the network, and produces the certificate the moment
+/// they reach the quorum.
+///
+/// The quorum is that of [meets_quorum](crate::meets_quorum): `params.k`
+/// distinct lottery indices, which can take fewer than `k` signatures when a
+/// party wins several lotteries.
+#[derive(Debug, Clone)]
+pub struct StreamingAggregator {
+    reg: ClosedKeyReg<D>,
+    avk: StmAggrVerificationKey<D>,
+    params: StmParameters,
+    msg: Vec<u8>,
+    sigs: Vec<StmSig>,
+    signers: BTreeSet<u64>,
+    indices: BTreeSet<u64>,
+    finalized: bool,
+}
+
+impl StreamingAggregator {
+    /// An aggregator of signatures over `msg` by the committee of `reg` under
+    /// `params`.
+    ///
+    /// Fails with [AggregationError::NoParties] if `reg` is empty.
+    pub fn new(
+        reg: &ClosedKeyReg<D>,
+        msg: &[u8],
+        params: StmParameters,
+    ) -> Result<Self, AggregationError> {
+        let avk = try_compute_avk_for_reg(reg).map_err(|_| AggregationError::NoParties)?;
+        Ok(Self {
+            reg: reg.clone(),
+            avk,
+            params,
+            msg: msg.to_vec(),
+            sigs: Vec::new(),
+            signers: BTreeSet::new(),
+            indices: BTreeSet::new(),
+            finalized: false,
+        })
+    }
+
+    /// Verify `sig` and count it towards the quorum, returning the certificate
+    /// if it is the signature that reaches it.
+    ///
+    /// Signatures that do not verify against the party registered at their
+    /// signer index, and repeated signatures of a party, are dropped. Once the
+    /// certificate has been returned, every further push returns `None`.
+    pub fn push(&mut self, sig: StmSig) -> Option<StmAggrSig<H>> {
+        if self.finalized || self.signers.contains(&sig.signer_index) {
+            return None;
+        }
+        let party = usize::try_from(sig.signer_index)
+            .ok()
+            .and_then(|i| self.reg.reg_parties.get(i))?;
+        if let Err(err) =
+            verify_single_sig(&sig, &self.msg, &party.0, party.1, &self.avk, self.params)
+        {
+            debug!(signer = sig.signer_index, ?err, "dropped invalid signature");
+            return None;
+        }
+
+        self.signers.insert(sig.signer_index);
+        self.indices.extend(sig.indexes.iter().copied());
+        self.sigs.push(sig);
+        if (self.indices.len() as u64) < self.params.k {
+            return None;
+        }
+        match aggregate_external(&self.reg, &self.sigs, &self.msg, self.params) {
+            Ok(msig) => {
+                self.finalized = true;
+                Some(msig)
+            }
+            Err(err) => {
+                debug!(?err, "quorum reached but aggregation failed");
+                None
+            }
+        }
+    }
+
+    /// Number of valid signatures counted so far.
+    pub fn len(&self) -> usize {
+        self.sigs.len()
+    }
+
+    /// Whether no valid signature has been counted yet.
+    pub fn is_empty(&self) -> bool {
+        self.sigs.is_empty()
+    }
+
+    /// Whether the certificate has been produced.
+    pub fn is_finalized(&self) -> bool {
+        self.finalized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{default_params, find_signatures, setup_committee, verify_aggregate_signature};
+
+    const MSG: &[u8] = b"streaming aggregation";
+
+    #[test]
+    fn certificate_appears_at_the_push_reaching_the_quorum() {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let sigs = find_signatures(MSG, &ps, &[0, 1, 2, 3]);
+        let mut aggregator = StreamingAggregator::new(&reg, MSG, params).unwrap();
+
+        // A signature over another message is dropped without being counted.
+        let forged = find_signatures(b"another message", &ps, &[0]);
+        assert_eq!(forged.len(), 1);
+        assert!(aggregator.push(forged[0].clone()).is_none());
+        assert!(aggregator.is_empty());
+
+        let mut indices = BTreeSet::new();
+        let mut msig = None;
+        for sig in sigs.iter().cloned() {
+            indices.extend(sig.indexes.iter().copied());
+            let reached = indices.len() as u64 >= params.k;
+            let pushed = aggregator.push(sig);
+            assert_eq!(pushed.is_some(), reached, "{} indices", indices.len());
+            if reached {
+                msig = pushed;
+                break;
+            }
+            // Pushing the same signer again changes nothing.
+            assert!(aggregator.push(sigs[0].clone()).is_none());
+        }
+        assert!(aggregator.is_finalized());
+        assert!(aggregator.len() < sigs.len());
+        assert!(aggregator.push(sigs[sigs.len() - 1].clone()).is_none());
+
+        let avk = try_compute_avk_for_reg(&reg).unwrap();
+        verify_aggregate_signature(MSG, &msig.unwrap(), &avk, params).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Attestations by a trusted off-chain verifier that it checked a certificate,
+//! for chains whose calldata cannot carry the certificate itself.
+//!
+//! This is a second layer on top of STM verification, not a replacement: the
+//! attestation is only as trustworthy as the key that signed it. Attestations
+//! are secp256k1 ECDSA signatures over the keccak-256 digest of the ABI
+//! encoded [VerificationData], so a contract can check them with `ecrecover`.
+
+use ethabi::ethereum_types::H256;
+use k256::ecdsa::{signature::hazmat::PrehashVerifier, Signature, SigningKey, VerifyingKey};
+use sha3::{Digest, Keccak256};
+
+use crate::{digest_to_h256, AttestationError, VerificationData, VerificationError, Verifier};
+
+/// Statement by a trusted verifier that the certificate digested in `data`
+/// verified over the message digested in `data`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attestation {
+    /// Digests of the message and of the certificate that was checked.
+    pub data: VerificationData,
+    /// Signature over the [attestation_digest] of `data`, as the 65 bytes
+    /// `r || s || v` expected by `ecrecover`, with `v` being 27 or 28.
+    pub signature: [u8; 65],
+}
+
+/// Keccak-256 digest of the [VerificationData::encode_abi] encoding of `data`,
+/// the digest an [Attestation] signs.
+pub fn attestation_digest(data: &VerificationData) -> H256 {
+    H256(Keccak256::digest(data.encode_abi()).into())
+}
+
+/// Check the certificate in `cert_bytes` over `msg` with `verifier` and, if it
+/// verifies, attest to it with `signing_key`.
+///
+/// The attestation commits to the same digests as the [VerificationData] of
+/// the message and certificate.
+// ECDSA signing of a 32-byte digest with a valid key cannot fail.
+#[allow(clippy::unwrap_used)]
+pub fn attest(
+    verifier: &Verifier,
+    msg: &[u8],
+    cert_bytes: &[u8],
+    signing_key: &SigningKey,
+) -> Result<Attestation, VerificationError> {
+    verifier.verify(msg, cert_bytes)?;
+    let data = VerificationData {
+        msg: digest_to_h256(msg),
+        msig: digest_to_h256(cert_bytes),
+    };
+    let (sig, recovery_id) = signing_key
+        .sign_prehash_recoverable(attestation_digest(&data).as_bytes())
+        .unwrap();
+    let mut signature = [0u8; 65];
+    signature[..64].copy_from_slice(&sig.to_bytes());
+    signature[64] = 27 + recovery_id.to_byte();
+    Ok(Attestation { data, signature })
+}
+
+/// Check that `attestation` is about the message digesting to `msg_digest`
+/// and was signed by `trusted_key`.
+///
+/// This checks no certificate: it only establishes that the holder of
+/// `trusted_key` vouched for one.
+pub fn verify_attestation(
+    msg_digest: H256,
+    attestation: &Attestation,
+    trusted_key: &VerifyingKey,
+) -> Result<(), AttestationError> {
+    if attestation.data.msg != msg_digest {
+        return Err(AttestationError::MessageMismatch);
+    }
+    let sig = Signature::from_slice(&attestation.signature[..64])
+        .map_err(|_| AttestationError::InvalidSignature)?;
+    trusted_key
+        .verify_prehash(attestation_digest(&attestation.data).as_bytes(), &sig)
+        .map_err(|_| AttestationError::InvalidSignature)
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::RecoveryId;
+
+    use super::*;
+    use crate::{
+        default_params, serialize_certificate, setup_committee, sign_with_mode, MessageMode,
+    };
+
+    const MSG: &[u8] = b"attestation tests";
+
+    /// An attestation over a certificate of a committee of four, and the key
+    /// that signed it.
+    fn attested() -> (Attestation, SigningKey) {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let msig = sign_with_mode(MessageMode::Raw, MSG, &ps).unwrap();
+        let verifier = Verifier::new(&reg, params).unwrap();
+        let key = SigningKey::from_slice(&[7; 32]).unwrap();
+
+        let cert = serialize_certificate(&msig);
+        assert!(attest(&verifier, b"another message", &cert, &key).is_err());
+        let attestation = attest(&verifier, MSG, &cert, &key).unwrap();
+        assert_eq!(attestation.data, VerificationData::new(MSG, &msig));
+        (attestation, key)
+    }
+
+    #[test]
+    fn valid_attestation_verifies_and_recovers_its_signer() {
+        let (attestation, key) = attested();
+        assert_eq!(
+            verify_attestation(digest_to_h256(MSG), &attestation, key.verifying_key()),
+            Ok(())
+        );
+
+        // What `ecrecover` does on chain.
+        let sig = Signature::from_slice(&attestation.signature[..64]).unwrap();
+        let recovery_id = RecoveryId::from_byte(attestation.signature[64] - 27).unwrap();
+        let recovered = VerifyingKey::recover_from_prehash(
+            attestation_digest(&attestation.data).as_bytes(),
+            &sig,
+            recovery_id,
+        )
+        .unwrap();
+        assert_eq!(&recovered, key.verifying_key());
+    }
+
+    #[test]
+    fn tampered_attestation_is_rejected() {
+        let (attestation, key) = attested();
+        let msg_digest = digest_to_h256(MSG);
+        let trusted = key.verifying_key();
+
+        assert_eq!(
+            verify_attestation(digest_to_h256(b"another message"), &attestation, trusted),
+            Err(AttestationError::MessageMismatch)
+        );
+
+        let mut other_cert = attestation.clone();
+        other_cert.data.msig = digest_to_h256(b"another certificate");
+        assert_eq!(
+            verify_attestation(msg_digest, &other_cert, trusted),
+            Err(AttestationError::InvalidSignature)
+        );
+
+        let mut flipped = attestation.clone();
+        flipped.signature[10] ^= 1;
+        assert_eq!(
+            verify_attestation(msg_digest, &flipped, trusted),
+            Err(AttestationError::InvalidSignature)
+        );
+
+        let untrusted = SigningKey::from_slice(&[8; 32]).unwrap();
+        assert_eq!(
+            verify_attestation(msg_digest, &attestation, untrusted.verifying_key()),
+            Err(AttestationError::InvalidSignature)
+        );
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Memoized verification for certificates that are seen more than once.
+
+use std::collections::HashMap;
+
+use ethabi::ethereum_types::H256;
+use mithril_stm::stm::{StmAggrSig, StmAggrVerificationKey, StmParameters};
+
+use crate::{
+    avk_fingerprint, verify_aggregate_signature, VerificationData, VerificationError, D, H,
+};
+
+/// Digests of the message, the certificate and the aggregate verification key
+/// a cached result was computed for.
+type CacheKey = (H256, H256, H256);
+
+/// Least recently used cache of verification results, for a relay that
+/// receives the same certificate many times as it is gossiped.
+///
+/// Results are keyed on the [VerificationData] digests of the message and
+/// certificate together with the [avk_fingerprint] of the committee. The cache
+/// only holds results for one committee and parameter set at a time: a call
+/// with a different AVK or different parameters drops every cached result.
+#[derive(Debug)]
+pub struct VerifierCache {
+    capacity: usize,
+    committee: Option<(H256, StmParameters)>,
+    entries: HashMap<CacheKey, (Result<(), VerificationError>, u64)>,
+    clock: u64,
+}
+
+impl VerifierCache {
+    /// An empty cache holding at most `capacity` results. A zero `capacity`
+    /// caches nothing.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            committee: None,
+            entries: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// [verify_aggregate_signature], unless the result for these arguments is
+    /// already cached.
+    pub fn verify(
+        &mut self,
+        msg: &[u8],
+        msig: &StmAggrSig<H>,
+        avk: &StmAggrVerificationKey<D>,
+        params: StmParameters,
+    ) -> Result<(), VerificationError> {
+        self.verify_with(msg, msig, avk, params, verify_aggregate_signature)
+    }
+
+    /// Like [VerifierCache::verify], but computing a missing result with
+    /// `verify` instead of [verify_aggregate_signature].
+    pub fn verify_with<F>(
+        &mut self,
+        msg: &[u8],
+        msig: &StmAggrSig<H>,
+        avk: &StmAggrVerificationKey<D>,
+        params: StmParameters,
+        verify: F,
+    ) -> Result<(), VerificationError>
+    where
+        F: FnOnce(
+            &[u8],
+            &StmAggrSig<H>,
+            &StmAggrVerificationKey<D>,
+            StmParameters,
+        ) -> Result<(), VerificationError>,
+    {
+        let fingerprint = avk_fingerprint(avk);
+        if self.committee != Some((fingerprint, params)) {
+            self.entries.clear();
+            self.committee = Some((fingerprint, params));
+        }
+
+        let data = VerificationData::new(msg, msig);
+        let key = (data.msg, data.msig, fingerprint);
+        self.clock += 1;
+        if let Some((result, last_used)) = self.entries.get_mut(&key) {
+            *last_used = self.clock;
+            return result.clone();
+        }
+
+        let result = verify(msg, msig, avk, params);
+        if self.capacity > 0 {
+            if self.entries.len() >= self.capacity {
+                self.evict_least_recently_used();
+            }
+            self.entries.insert(key, (result.clone(), self.clock));
+        }
+        result
+    }
+
+    /// Number of cached results.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no result is cached.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drop every cached result.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.committee = None;
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, (_, last_used))| *last_used)
+            .map(|(key, _)| *key);
+        if let Some(key) = oldest {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::{compute_avk_for_stake, default_params, generate_aggregate_signatures};
+
+    const MSG: &[u8] = b"cached certificate";
+
+    #[test]
+    fn hits_skip_verification_until_the_avk_changes() {
+        let params = default_params();
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        let avk = compute_avk_for_stake(vec![1; 4], params);
+        let other_avk = compute_avk_for_stake(vec![1; 5], params);
+
+        let calls = Cell::new(0);
+        let counting = |msg: &[u8], msig: &StmAggrSig<H>, avk: &_, params| {
+            calls.set(calls.get() + 1);
+            verify_aggregate_signature(msg, msig, avk, params)
+        };
+        let mut cache = VerifierCache::new(8);
+
+        assert_eq!(
+            cache.verify_with(MSG, &msig, &avk, params, counting),
+            Ok(())
+        );
+        assert_eq!(calls.get(), 1);
+        assert_eq!(
+            cache.verify_with(MSG, &msig, &avk, params, counting),
+            Ok(())
+        );
+        assert_eq!(calls.get(), 1);
+        assert_eq!(cache.len(), 1);
+
+        assert!(cache
+            .verify_with(MSG, &msig, &other_avk, params, counting)
+            .is_err());
+        assert_eq!(calls.get(), 2);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(
+            cache.verify_with(MSG, &msig, &avk, params, counting),
+            Ok(())
+        );
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn least_recently_used_result_is_evicted() {
+        let params = default_params();
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        let avk = compute_avk_for_stake(vec![1; 4], params);
+        let calls = Cell::new(0);
+        let counting = |_: &[u8], _: &StmAggrSig<H>, _: &_, _| {
+            calls.set(calls.get() + 1);
+            Ok(())
+        };
+
+        let mut cache = VerifierCache::new(2);
+        for msg in [b"a", b"b", b"a", b"c", b"a", b"b"] {
+            cache
+                .verify_with(msg, &msig, &avk, params, counting)
+                .unwrap();
+        }
+        // "b" was evicted by "c", while "a" stayed in use.
+        assert_eq!(calls.get(), 4);
+        assert_eq!(cache.len(), 2);
+
+        let mut disabled = VerifierCache::new(0);
+        disabled
+            .verify_with(MSG, &msig, &avk, params, counting)
+            .unwrap();
+        assert!(disabled.is_empty());
+    }
+}
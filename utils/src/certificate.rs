@@ -0,0 +1,642 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::BTreeSet, io::ErrorKind};
+
+use base64::{
+    engine::{
+        general_purpose::{STANDARD, URL_SAFE},
+        GeneralPurpose,
+    },
+    Engine,
+};
+use bincode::Options;
+use blake2::digest::{Digest, FixedOutput};
+use mithril_stm::{
+    key_reg::ClosedKeyReg,
+    stm::{StmAggrSig, StmAggrVerificationKey, StmParameters, StmSigRegParty},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{CertificateError, Stake, D, H, MAX_CERTIFICATE_LEN};
+
+/// Version of the wire format written by [serialize_certificate].
+///
+/// Bump this whenever the encoding of the certificate changes, e.g. with a
+/// mithril-stm upgrade, so that blobs in the old format are rejected instead
+/// of misparsed.
+pub const CERTIFICATE_VERSION: u8 = 1;
+
+/// Encode `msig` as a byte array, e.g. for transport in contract calldata.
+///
+/// The encoding starts with the [CERTIFICATE_VERSION] byte. mithril-stm's own
+/// `StmAggrSig::to_bytes` assumes every party signature has the same length,
+/// which does not hold once parties win a different number of lotteries, so
+/// the certificate itself is encoded with bincode instead.
+pub fn serialize_certificate(msig: &StmAggrSig<H>) -> Vec<u8> {
+    encode_versioned(msig)
+}
+
+/// Decode a certificate produced by [serialize_certificate].
+///
+/// `bytes` must hold exactly one certificate: truncated input and trailing
+/// bytes are both rejected, and a version byte other than
+/// [CERTIFICATE_VERSION] is reported as [CertificateError::UnsupportedVersion].
+pub fn deserialize_certificate(bytes: &[u8]) -> Result<StmAggrSig<H>, CertificateError> {
+    decode_versioned(bytes)
+}
+
+/// Decode a certificate like [deserialize_certificate], then check that it
+/// was produced under `expected_params` by a committee of `expected_parties`
+/// parties.
+///
+/// Every lottery index must lie in `0..m` and reach a quorum of `k` distinct
+/// indices, and every signer must have a distinct Merkle tree index below
+/// `expected_parties`. This catches certificates replayed from a committee or
+/// parameter set other than the expected one before any signature is checked.
+pub fn deserialize_certificate_checked(
+    bytes: &[u8],
+    expected_params: StmParameters,
+    expected_parties: usize,
+) -> Result<StmAggrSig<H>, CertificateError> {
+    let msig = deserialize_certificate(bytes)?;
+    let mismatch = |reason: String| CertificateError::StructureMismatch { reason };
+
+    let mut signers = BTreeSet::new();
+    let mut indices = BTreeSet::new();
+    for sig_reg in signatures(&msig) {
+        let signer = sig_reg.sig.signer_index;
+        if signer >= expected_parties as u64 {
+            return Err(mismatch(format!(
+                "signer index {signer} is out of range for {expected_parties} parties"
+            )));
+        }
+        if !signers.insert(signer) {
+            return Err(mismatch(format!("signer index {signer} appears twice")));
+        }
+        for &index in &sig_reg.sig.indexes {
+            if index >= expected_params.m {
+                return Err(mismatch(format!(
+                    "lottery index {index} is out of range for m = {}",
+                    expected_params.m
+                )));
+            }
+            indices.insert(index);
+        }
+    }
+    if (indices.len() as u64) < expected_params.k {
+        return Err(mismatch(format!(
+            "{} distinct lottery indices are fewer than k = {}",
+            indices.len(),
+            expected_params.k
+        )));
+    }
+    Ok(msig)
+}
+
+/// Encode `avk` as a byte array, e.g. for verifiers that do not hold the keys
+/// of the committee.
+pub fn serialize_avk(avk: &StmAggrVerificationKey<D>) -> Vec<u8> {
+    encode(avk)
+}
+
+/// Decode an aggregate verification key produced by [serialize_avk], with the
+/// same strictness as [deserialize_certificate].
+pub fn deserialize_avk(bytes: &[u8]) -> Result<StmAggrVerificationKey<D>, CertificateError> {
+    decode_exact(bytes)
+}
+
+/// Encode `msig` as a `0x`-prefixed hex string of its
+/// [serialize_certificate] bytes, e.g. for pasting into a transaction.
+pub fn certificate_to_hex(msig: &StmAggrSig<H>) -> String {
+    format!("0x{}", hex::encode(serialize_certificate(msig)))
+}
+
+/// Decode a certificate from hex, with or without a `0x` prefix.
+pub fn certificate_from_hex(s: &str) -> Result<StmAggrSig<H>, CertificateError> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    let bytes = hex::decode(digits).map_err(|_| CertificateError::InvalidHex)?;
+    deserialize_certificate(&bytes)
+}
+
+/// Alphabet of the base64 encoding written by [certificate_to_base64].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Base64Alphabet {
+    /// The standard alphabet, with `+` and `/`.
+    #[default]
+    Standard,
+    /// The URL and filename safe alphabet, with `-` and `_`.
+    UrlSafe,
+}
+
+impl Base64Alphabet {
+    fn engine(self) -> &'static GeneralPurpose {
+        match self {
+            Self::Standard => &STANDARD,
+            Self::UrlSafe => &URL_SAFE,
+        }
+    }
+}
+
+/// Encode `msig` as a padded base64 string of its [serialize_certificate]
+/// bytes in `alphabet`, a third shorter than [certificate_to_hex] for
+/// off-chain APIs.
+pub fn certificate_to_base64(msig: &StmAggrSig<H>, alphabet: Base64Alphabet) -> String {
+    alphabet.engine().encode(serialize_certificate(msig))
+}
+
+/// Decode a certificate from padded base64 in `alphabet`, rejecting missing
+/// or excess padding.
+pub fn certificate_from_base64(
+    s: &str,
+    alphabet: Base64Alphabet,
+) -> Result<StmAggrSig<H>, CertificateError> {
+    let bytes = alphabet
+        .engine()
+        .decode(s)
+        .map_err(|_| CertificateError::InvalidBase64)?;
+    deserialize_certificate(&bytes)
+}
+
+/// Upper bound on the length in bytes of the [serialize_certificate] encoding
+/// of a certificate under `params` from a committee of `n_signers` parties,
+/// all of which may sign, e.g. for gas budgeting before generating it.
+///
+/// The bound follows the bincode layout of mithril-stm's aggregate: the
+/// version byte and the signature count, then per signature a 48-byte
+/// signature, its lottery indices, its signer index and the 96-byte key and
+/// stake of its party, then a Merkle batch proof. A certificate cannot hold
+/// more than the `params.m` indices of the lottery, and each signature holds
+/// at least one, so there are at most `min(n_signers, m)` signatures. The
+/// proof is counted as one full path per signature, although paths share
+/// nodes in practice, so actual certificates are usually much shorter.
+pub fn estimate_certificate_size(n_signers: usize, params: &StmParameters) -> usize {
+    const LEN: usize = 8;
+    const WORD: usize = 8;
+    const SIGMA: usize = 48;
+    const VK: usize = 96;
+    const HASH: usize = 32;
+
+    let m = usize::try_from(params.m).unwrap_or(usize::MAX);
+    let signatures = n_signers.min(m);
+    let depth = n_signers.next_power_of_two().trailing_zeros() as usize;
+    let per_signature = SIGMA + LEN + WORD + VK + WORD;
+    let batch_proof = LEN
+        + signatures
+            .saturating_mul(depth)
+            .saturating_mul(LEN + HASH)
+            .saturating_add(LEN)
+            .saturating_add(signatures.saturating_mul(WORD));
+
+    1usize
+        .saturating_add(LEN)
+        .saturating_add(signatures.saturating_mul(per_signature))
+        .saturating_add(m.saturating_mul(WORD))
+        .saturating_add(batch_proof)
+}
+
+/// Number of party signatures aggregated in `msig`, without verifying any.
+///
+/// mithril-stm keeps only as many signatures as the quorum needs, so this can
+/// be fewer than the number of lottery winners.
+pub fn signature_count(msig: &StmAggrSig<H>) -> usize {
+    signatures(msig).len()
+}
+
+/// Stake of the parties of `closed_reg` that contributed a signature to
+/// `msig`, together with the total stake of the registration, as
+/// `(participating, total)`.
+///
+/// A party counts once however many lotteries it won, and signatures from
+/// parties that are not registered at their index in `closed_reg` are
+/// ignored.
+pub fn participating_stake(msig: &StmAggrSig<H>, closed_reg: &ClosedKeyReg<D>) -> (Stake, Stake) {
+    let mut signers = BTreeSet::new();
+    for sig_reg in signatures(msig) {
+        let registered = usize::try_from(sig_reg.sig.signer_index)
+            .ok()
+            .and_then(|i| closed_reg.reg_parties.get(i));
+        if registered == Some(&sig_reg.reg_party) {
+            signers.insert(sig_reg.sig.signer_index);
+        }
+    }
+    let participating = signers
+        .into_iter()
+        .filter_map(|i| closed_reg.reg_parties.get(i as usize))
+        .fold(0, |total: Stake, party| total.saturating_add(party.1));
+    (participating, closed_reg.total_stake)
+}
+
+/// Check the [CERTIFICATE_VERSION] byte at the start of `bytes`, then decode
+/// exactly one bincode value from the rest.
+pub(crate) fn decode_versioned<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CertificateError> {
+    match bytes.split_first() {
+        None => Err(CertificateError::Truncated),
+        Some((&CERTIFICATE_VERSION, rest)) => decode_exact(rest),
+        Some((&version, _)) => Err(CertificateError::UnsupportedVersion(version)),
+    }
+}
+
+/// Prefix the bincode encoding of a certificate hashed with any digest with
+/// [CERTIFICATE_VERSION], as [serialize_certificate] does.
+pub(crate) fn encode_versioned<T: Serialize>(msig: &T) -> Vec<u8> {
+    let mut bytes = vec![CERTIFICATE_VERSION];
+    bytes.extend(encode(msig));
+    bytes
+}
+
+/// Encode a certificate or an aggregate verification key with bincode.
+// Both only hold byte arrays, integers and sequences of known length, which
+// bincode always encodes, so this never panics; empty bytes must not be
+// handed out in place of a failure either.
+#[allow(clippy::expect_used)]
+pub(crate) fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+    bincode::serialize(value).expect("certificates and keys always encode")
+}
+
+/// Decode exactly one bincode value from `bytes`.
+///
+/// Decoding reads at most [MAX_CERTIFICATE_LEN] bytes, more than any
+/// certificate holds, so a forged length prefix cannot make it allocate past
+/// that; hitting the limit is reported as [CertificateError::Malformed].
+pub(crate) fn decode_exact<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CertificateError> {
+    // The same fixed-width integers as `bincode::serialize`; trailing bytes
+    // are left in the reader and reported below.
+    let options = bincode::DefaultOptions::new()
+        .with_limit(MAX_CERTIFICATE_LEN as u64)
+        .with_fixint_encoding()
+        .allow_trailing_bytes();
+    let mut reader = bytes;
+    let value = options
+        .deserialize_from(&mut reader)
+        .map_err(|err| match *err {
+            bincode::ErrorKind::Io(ref err) if err.kind() == ErrorKind::UnexpectedEof => {
+                CertificateError::Truncated
+            }
+            _ => CertificateError::Malformed,
+        })?;
+    if !reader.is_empty() {
+        return Err(CertificateError::TrailingBytes(reader.len()));
+    }
+    Ok(value)
+}
+
+/// Party signatures embedded in `msig`.
+///
+/// mithril-stm keeps these private, so they are recovered from the serde
+/// encoding of the aggregate, whose first field is the signature list.
+pub(crate) fn signatures<D>(msig: &StmAggrSig<D>) -> Vec<StmSigRegParty>
+where
+    D: Clone + Digest + FixedOutput,
+    StmAggrSig<D>: Serialize,
+{
+    // Encoding an in-memory aggregate and decoding a prefix of it cannot fail.
+    bincode::serialize(msig)
+        .and_then(|bytes| bincode::deserialize::<(Vec<StmSigRegParty>,)>(&bytes))
+        .map(|(sigs,)| sigs)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::{collection, prelude::*};
+
+    use super::*;
+    use crate::{
+        compute_avk_for_stake, default_params, find_signatures, generate_aggregate_signatures,
+        generate_aggregate_signatures_for_stake, setup_committee, setup_equal_parties,
+        sign_with_mode, verify_aggregate_signature, MessageMode,
+    };
+
+    const MSG: &[u8] = b"certificate round trip";
+
+    #[test]
+    fn certificate_round_trips_and_verifies() {
+        let params = default_params();
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        let avk = compute_avk_for_stake(vec![1; 4], params);
+
+        let bytes = serialize_certificate(&msig);
+        assert_eq!(bytes[0], CERTIFICATE_VERSION);
+        let decoded = deserialize_certificate(&bytes).unwrap();
+        assert_eq!(serialize_certificate(&decoded), bytes);
+        verify_aggregate_signature(MSG, &decoded, &avk, params).unwrap();
+    }
+
+    #[test]
+    fn truncated_certificate_is_rejected() {
+        let msig = generate_aggregate_signatures(MSG, default_params(), 4).unwrap();
+        let bytes = serialize_certificate(&msig);
+
+        assert_eq!(
+            deserialize_certificate(&[]).err(),
+            Some(CertificateError::Truncated)
+        );
+        for len in [1, bytes.len() / 2, bytes.len() - 1] {
+            assert!(
+                deserialize_certificate(&bytes[..len]).is_err(),
+                "{len} bytes"
+            );
+        }
+        assert_eq!(
+            deserialize_certificate(&bytes[..bytes.len() - 1]).err(),
+            Some(CertificateError::Truncated)
+        );
+    }
+
+    #[test]
+    fn forged_length_prefixes_are_rejected() {
+        for count in [u64::MAX, MAX_CERTIFICATE_LEN as u64 + 1] {
+            let mut bytes = vec![CERTIFICATE_VERSION];
+            bytes.extend_from_slice(&count.to_le_bytes());
+            bytes.extend_from_slice(&[0; 64]);
+            assert!(matches!(
+                deserialize_certificate(&bytes).err(),
+                Some(CertificateError::Truncated | CertificateError::Malformed)
+            ));
+        }
+    }
+
+    #[test]
+    fn trailing_bytes_are_rejected() {
+        let msig = generate_aggregate_signatures(MSG, default_params(), 4).unwrap();
+        let mut bytes = serialize_certificate(&msig);
+        bytes.extend_from_slice(&[0xde, 0xad]);
+
+        assert_eq!(
+            deserialize_certificate(&bytes).err(),
+            Some(CertificateError::TrailingBytes(2))
+        );
+    }
+
+    #[test]
+    fn hex_round_trips_with_or_without_prefix() {
+        let msig = generate_aggregate_signatures(MSG, default_params(), 4).unwrap();
+        let hex = certificate_to_hex(&msig);
+        assert!(hex.starts_with("0x"));
+
+        let bytes = serialize_certificate(&msig);
+        for s in [hex.as_str(), &hex[2..]] {
+            assert_eq!(
+                serialize_certificate(&certificate_from_hex(s).unwrap()),
+                bytes
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_hex_is_rejected() {
+        for s in ["0x0", "0xzz", "not hex"] {
+            assert_eq!(
+                certificate_from_hex(s).err(),
+                Some(CertificateError::InvalidHex)
+            );
+        }
+    }
+
+    #[test]
+    fn participating_stake_counts_each_signer_once() {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let msig = sign_with_mode(MessageMode::Raw, MSG, &ps).unwrap();
+
+        let (participating, total) = participating_stake(&msig, &reg);
+        assert_eq!(total, 4);
+        assert!((1..=total).contains(&participating));
+        assert_eq!(participating, signature_count(&msig) as Stake);
+    }
+
+    proptest! {
+        // Every case sets up a committee and runs its lottery, so keep `m`
+        // small and the number of cases low.
+        #![proptest_config(ProptestConfig::with_cases(24))]
+
+        #[test]
+        fn serialization_is_lossless(
+            stake in collection::vec(1..=1_000u64, 1..=32),
+            msg in collection::vec(any::<u8>(), 1..=256),
+        ) {
+            let params = StmParameters { k: 8, m: 64, phi_f: 0.5 };
+            let msig = generate_aggregate_signatures_for_stake(&msg, params, stake.clone());
+            let msig = msig.map_err(|err| TestCaseError::fail(err.to_string()))?;
+            let avk = compute_avk_for_stake(stake, params);
+
+            let bytes = serialize_certificate(&msig);
+            let decoded = deserialize_certificate(&bytes).unwrap();
+            prop_assert_eq!(serialize_certificate(&decoded), bytes);
+            prop_assert!(verify_aggregate_signature(&msg, &msig, &avk, params).is_ok());
+            prop_assert!(verify_aggregate_signature(&msg, &decoded, &avk, params).is_ok());
+        }
+    }
+
+    #[test]
+    fn checked_decoding_rejects_other_parameters_and_committees() {
+        let params = default_params();
+        let bytes = serialize_certificate(&generate_aggregate_signatures(MSG, params, 4).unwrap());
+        let mismatch = |params, parties| {
+            matches!(
+                deserialize_certificate_checked(&bytes, params, parties),
+                Err(CertificateError::StructureMismatch { .. })
+            )
+        };
+
+        assert!(deserialize_certificate_checked(&bytes, params, 4).is_ok());
+        assert!(mismatch(StmParameters { k: 2000, ..params }, 4));
+        assert!(mismatch(StmParameters { m: 100, ..params }, 4));
+        assert!(mismatch(params, 2));
+    }
+
+    #[test]
+    fn signature_count_matches_the_winners_when_all_are_needed() {
+        // The lottery does not depend on `k`, so setting it to the number of
+        // indices won by the whole committee makes every winner necessary.
+        let probe = StmParameters {
+            k: 1,
+            ..default_params()
+        };
+        let winners = find_signatures(MSG, &setup_equal_parties(probe, 4), &[0, 1, 2, 3]);
+        let won: BTreeSet<u64> = winners
+            .iter()
+            .flat_map(|sig| sig.indexes.iter().copied())
+            .collect();
+        let params = StmParameters {
+            k: won.len() as u64,
+            ..probe
+        };
+
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        assert_eq!(signature_count(&msig), winners.len());
+        let fewer = generate_aggregate_signatures(MSG, probe, 4).unwrap();
+        assert_eq!(signature_count(&fewer), 1);
+    }
+
+    #[test]
+    fn future_format_versions_are_rejected() {
+        let msig = generate_aggregate_signatures(MSG, default_params(), 4).unwrap();
+        let mut bytes = serialize_certificate(&msig);
+        assert_eq!(bytes[0], CERTIFICATE_VERSION);
+
+        bytes[0] = CERTIFICATE_VERSION + 1;
+        assert_eq!(
+            deserialize_certificate(&bytes).err(),
+            Some(CertificateError::UnsupportedVersion(
+                CERTIFICATE_VERSION + 1
+            ))
+        );
+        assert_eq!(
+            deserialize_certificate(&[]).err(),
+            Some(CertificateError::Truncated)
+        );
+    }
+
+    #[test]
+    fn base64_round_trips_in_both_alphabets() {
+        let msig = generate_aggregate_signatures(MSG, default_params(), 4).unwrap();
+        for alphabet in [Base64Alphabet::Standard, Base64Alphabet::UrlSafe] {
+            let encoded = certificate_to_base64(&msig, alphabet);
+            let decoded = certificate_from_base64(&encoded, alphabet).unwrap();
+            assert_eq!(
+                serialize_certificate(&decoded),
+                serialize_certificate(&msig)
+            );
+        }
+        assert_eq!(Base64Alphabet::default(), Base64Alphabet::Standard);
+    }
+
+    #[test]
+    fn malformed_base64_is_rejected() {
+        for alphabet in [Base64Alphabet::Standard, Base64Alphabet::UrlSafe] {
+            // Invalid characters, missing padding and excess padding.
+            for s in ["AA!A", "AAA", "AAAA=", "AA=A"] {
+                assert_eq!(
+                    certificate_from_base64(s, alphabet).err(),
+                    Some(CertificateError::InvalidBase64),
+                    "{s:?}"
+                );
+            }
+            // Valid base64 that does not hold a certificate.
+            assert!(matches!(
+                certificate_from_base64("AQ==", alphabet).err(),
+                Some(CertificateError::Truncated)
+            ));
+        }
+        // Characters of one alphabet are invalid in the other.
+        assert_eq!(
+            certificate_from_base64("AA+/", Base64Alphabet::UrlSafe).err(),
+            Some(CertificateError::InvalidBase64)
+        );
+        assert_eq!(
+            certificate_from_base64("AA-_", Base64Alphabet::Standard).err(),
+            Some(CertificateError::InvalidBase64)
+        );
+    }
+
+    #[test]
+    fn size_estimate_bounds_actual_certificates() {
+        // With `k` at the number of indices won by the whole committee, every
+        // winner and every index it won ends up in the certificate.
+        let probe = StmParameters {
+            k: 1,
+            ..default_params()
+        };
+        let winners = find_signatures(MSG, &setup_equal_parties(probe, 4), &[0, 1, 2, 3]);
+        let won: BTreeSet<u64> = winners
+            .iter()
+            .flat_map(|sig| sig.indexes.iter().copied())
+            .collect();
+        let all_needed = StmParameters {
+            k: won.len() as u64,
+            ..probe
+        };
+
+        for (params, nparties) in [
+            (default_params(), 1),
+            (default_params(), 4),
+            (default_params(), 16),
+            (all_needed, 4),
+        ] {
+            let msig = generate_aggregate_signatures(MSG, params, nparties).unwrap();
+            let actual = serialize_certificate(&msig).len();
+            assert!(
+                estimate_certificate_size(nparties, &params) >= actual,
+                "{nparties} parties, {actual} bytes"
+            );
+        }
+    }
+
+    #[cfg(feature = "host")]
+    #[test]
+    fn size_estimate_bounds_the_test_vector() {
+        let path =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/test_vector.json");
+        let vector: crate::TestVector =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let estimate = estimate_certificate_size(vector.stake.len(), &vector.params);
+        assert!(estimate >= vector.certificate.len());
+    }
+
+    /// `fixtures/mithril_aggregate.hex` holds the aggregate of four parties
+    /// of stake 1 over [MSG] under the default parameters and seed, as
+    /// encoded by mithril-stm's `StmAggrSig::to_bytes`. It is written by
+    /// `cargo run --example mithril_aggregate`, which only calls mithril-stm.
+    #[test]
+    fn encoding_diverges_from_mithril_to_bytes() {
+        let path =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/mithril_aggregate.hex");
+        let mithril = hex::decode(std::fs::read_to_string(path).unwrap().trim()).unwrap();
+        let msig = generate_aggregate_signatures(MSG, default_params(), 4).unwrap();
+        assert_eq!(msig.to_bytes(), mithril);
+        let ours = serialize_certificate(&msig);
+        let sigs = signatures(&msig);
+        assert!(sigs.len() > 1);
+
+        let be =
+            |bytes: &[u8], at: usize| u64::from_be_bytes(bytes[at..at + 8].try_into().unwrap());
+        let le =
+            |bytes: &[u8], at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        // Both encode a signature in 168 bytes plus 8 per lottery index.
+        let len = |sig_reg: &StmSigRegParty| 168 + 8 * sig_reg.sig.indexes.len();
+
+        // Ours opens with the version byte and a little-endian signature
+        // count, mithril's with a big-endian count and the length of the
+        // first signature, which it assumes for every other one.
+        assert_eq!(ours[0], CERTIFICATE_VERSION);
+        assert_eq!(le(&ours, 1), sigs.len() as u64);
+        assert_eq!(be(&mithril, 0), sigs.len() as u64);
+        assert_eq!(be(&mithril, 8), len(&sigs[0]) as u64);
+
+        // Every signature is prefixed with its own number of indices, little
+        // endian after sigma in ours and big endian after the party's key and
+        // stake in mithril's.
+        let (mut at_ours, mut at_mithril) = (9, 16);
+        for sig_reg in &sigs {
+            let indices = sig_reg.sig.indexes.len() as u64;
+            assert_eq!(le(&ours, at_ours + 48), indices);
+            assert_eq!(be(&mithril, at_mithril + 104), indices);
+            at_ours += len(sig_reg);
+            at_mithril += len(sig_reg);
+        }
+
+        // The signatures win different numbers of indices, so mithril's
+        // `from_bytes` misreads every one after the first, and panics on this
+        // fixture, while ours decodes its own encoding.
+        assert!(sigs.iter().any(|sig_reg| len(sig_reg) != len(&sigs[0])));
+        assert_eq!(
+            deserialize_certificate(&mithril).err(),
+            Some(CertificateError::UnsupportedVersion(mithril[0]))
+        );
+        let decoded = deserialize_certificate(&ours).unwrap();
+        assert_eq!(serialize_certificate(&decoded), ours);
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! JSON snapshots of a committee, for publishing the full verification
+//! context of its certificates.
+
+use mithril_stm::{
+    key_reg::ClosedKeyReg,
+    stm::{StmParameters, StmVerificationKey},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    avk_fingerprint,
+    registration::{close_parties, reg_party},
+    testvectors::hex_bytes,
+    try_compute_avk_for_reg, CommitteeSnapshotError, Stake, D,
+};
+
+/// Serde form of [export_committee].
+#[derive(Serialize, Deserialize)]
+struct CommitteeSnapshot {
+    params: StmParameters,
+    total_stake: Stake,
+    #[serde(with = "hex_bytes")]
+    avk_fingerprint: Vec<u8>,
+    parties: Vec<PartySnapshot>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PartySnapshot {
+    #[serde(with = "hex_bytes")]
+    vk: Vec<u8>,
+    stake: Stake,
+}
+
+/// Describe `reg` and the `params` its certificates are produced under as
+/// JSON: the parameters, the total stake, the [avk_fingerprint] and the
+/// verification key and stake of every party, with bytes hex-encoded.
+///
+/// Parties are listed in the sorted order of the Merkle tree leaves.
+// A registration that was closed has at least one party, and a snapshot of
+// numbers and strings always converts to a JSON value, so this never panics;
+// a snapshot without a fingerprint must not be published either.
+#[allow(clippy::expect_used)]
+pub fn export_committee(reg: &ClosedKeyReg<D>, params: StmParameters) -> serde_json::Value {
+    let avk = try_compute_avk_for_reg(reg).expect("closed registrations hold a party");
+    let snapshot = CommitteeSnapshot {
+        params,
+        total_stake: reg.total_stake,
+        avk_fingerprint: avk_fingerprint(&avk).0.to_vec(),
+        parties: reg
+            .reg_parties
+            .iter()
+            .map(|party| PartySnapshot {
+                vk: party.0.to_bytes().to_vec(),
+                stake: party.1,
+            })
+            .collect(),
+    };
+    serde_json::to_value(snapshot).expect("committee snapshots always convert to JSON")
+}
+
+/// Rebuild the registration described by an [export_committee] snapshot.
+///
+/// The rebuilt registration must have the fingerprint recorded in the
+/// snapshot, so a snapshot whose parties or stakes were altered is rejected
+/// with [CommitteeSnapshotError::FingerprintMismatch].
+pub fn import_committee(
+    value: &serde_json::Value,
+) -> Result<ClosedKeyReg<D>, CommitteeSnapshotError> {
+    let snapshot = CommitteeSnapshot::deserialize(value).map_err(|err| {
+        CommitteeSnapshotError::InvalidJson {
+            reason: err.to_string(),
+        }
+    })?;
+    let reg_parties = snapshot
+        .parties
+        .iter()
+        .enumerate()
+        .map(|(index, party)| {
+            // mithril-stm panics on keys shorter than its 96-byte encoding.
+            if party.vk.len() != 96 {
+                return Err(CommitteeSnapshotError::InvalidKey { index });
+            }
+            StmVerificationKey::from_bytes(&party.vk)
+                .map(|vk| reg_party(vk, party.stake))
+                .map_err(|_| CommitteeSnapshotError::InvalidKey { index })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let reg = close_parties(reg_parties)?;
+    let avk = try_compute_avk_for_reg(&reg)?;
+    if avk_fingerprint(&avk).as_bytes() != snapshot.avk_fingerprint.as_slice() {
+        return Err(CommitteeSnapshotError::FingerprintMismatch);
+    }
+    Ok(reg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{default_params, setup_committee};
+
+    #[test]
+    fn snapshot_round_trips_to_the_same_registration() {
+        let params = default_params();
+        let (_, reg) = setup_committee(params, vec![5, 1, 3, 2]);
+        let snapshot = export_committee(&reg, params);
+        assert_eq!(snapshot["total_stake"], 11);
+        assert_eq!(snapshot["parties"].as_array().unwrap().len(), 4);
+
+        let imported = import_committee(&snapshot).unwrap();
+        let fingerprint = |reg| avk_fingerprint(&try_compute_avk_for_reg(reg).unwrap());
+        assert_eq!(fingerprint(&imported), fingerprint(&reg));
+        assert_eq!(imported.reg_parties, reg.reg_parties);
+        assert_eq!(imported.total_stake, reg.total_stake);
+    }
+
+    #[test]
+    fn altered_snapshots_are_rejected() {
+        let params = default_params();
+        let (_, reg) = setup_committee(params, vec![1; 4]);
+        let snapshot = export_committee(&reg, params);
+
+        let mut restaked = snapshot.clone();
+        restaked["parties"][0]["stake"] = 2.into();
+        assert_eq!(
+            import_committee(&restaked).err(),
+            Some(CommitteeSnapshotError::FingerprintMismatch)
+        );
+
+        let mut bad_key = snapshot.clone();
+        bad_key["parties"][1]["vk"] = "0x00".into();
+        assert_eq!(
+            import_committee(&bad_key).err(),
+            Some(CommitteeSnapshotError::InvalidKey { index: 1 })
+        );
+
+        assert!(matches!(
+            import_committee(&serde_json::json!({ "parties": [] })),
+            Err(CommitteeSnapshotError::InvalidJson { .. })
+        ));
+    }
+}
@@ -0,0 +1,683 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{error::Error, fmt};
+
+/// Reasons an aggregate signature is rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerificationError {
+    /// The certificate won a lottery index that does not exist under the
+    /// verifier's `m`, so it was produced with different parameters.
+    ParameterMismatch { index: u64, m: u64 },
+
+    /// The certificate wins only `indices` distinct lottery indices, fewer
+    /// than the `k` required for a quorum.
+    BelowQuorum { indices: u64, k: u64 },
+
+    /// The aggregate signature does not verify against the aggregate
+    /// verification key, for the reason reported by mithril-stm. This includes
+    /// certificates produced by a different committee.
+    InvalidSignature { reason: String },
+
+    /// The certificate bytes could not be decoded.
+    Deserialization(CertificateError),
+
+    /// The aggregate verification key bytes could not be decoded.
+    AvkDeserialization(CertificateError),
+
+    /// The message is empty, which is almost certainly a caller bug.
+    EmptyMessage,
+
+    /// The message is `len` bytes long, more than the `max` accepted.
+    MessageTooLong { len: usize, max: usize },
+
+    /// The registration to verify against holds no party.
+    EmptyCommittee,
+
+    /// The certificate was signed under the registration with the `actual`
+    /// fingerprint but checked against the one with the `expected`
+    /// fingerprint, e.g. because the stake distributions differ. Both are
+    /// [avk_fingerprint](crate::avk_fingerprint) digests.
+    RegistrationMismatch {
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ParameterMismatch { index, m } => write!(
+                f,
+                "certificate wins lottery index {index}, which does not exist for m = {m}"
+            ),
+            Self::BelowQuorum { indices, k } => write!(
+                f,
+                "certificate wins {indices} distinct lottery indices, fewer than k = {k}"
+            ),
+            Self::InvalidSignature { reason } => write!(f, "invalid aggregate signature: {reason}"),
+            Self::Deserialization(_) => write!(f, "cannot decode certificate"),
+            Self::AvkDeserialization(_) => write!(f, "cannot decode aggregate verification key"),
+            Self::EmptyMessage => write!(f, "message is empty"),
+            Self::MessageTooLong { len, max } => {
+                write!(
+                    f,
+                    "message is {len} bytes long, more than the {max} accepted"
+                )
+            }
+            Self::EmptyCommittee => write!(f, "committee holds no party"),
+            Self::RegistrationMismatch { expected, actual } => write!(
+                f,
+                "certificate was signed under registration 0x{} but checked against 0x{}",
+                hex::encode(actual),
+                hex::encode(expected)
+            ),
+        }
+    }
+}
+
+impl Error for VerificationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Deserialization(err) | Self::AvkDeserialization(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<CertificateError> for VerificationError {
+    fn from(err: CertificateError) -> Self {
+        Self::Deserialization(err)
+    }
+}
+
+/// Reasons a set of STM parameters is rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamError {
+    /// `phi_f` is not a fraction in `[0, 1]`.
+    PhiFOutOfRange { phi_f: f64 },
+
+    /// No lottery win would be required for a quorum.
+    ZeroK,
+
+    /// Parties would take part in no lottery.
+    ZeroM,
+
+    /// More lottery wins are required than there are lotteries.
+    KExceedsM { k: u64, m: u64 },
+
+    /// The environment variable `var` holds a `value` that is not a number.
+    InvalidEnv { var: &'static str, value: String },
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PhiFOutOfRange { phi_f } => write!(f, "phi_f = {phi_f} is not in [0, 1]"),
+            Self::ZeroK => write!(f, "k must be at least 1"),
+            Self::ZeroM => write!(f, "m must be at least 1"),
+            Self::KExceedsM { k, m } => write!(f, "k = {k} exceeds m = {m}"),
+            Self::InvalidEnv { var, value } => write!(f, "{var} = {value:?} is not a number"),
+        }
+    }
+}
+
+impl Error for ParamError {}
+
+/// Reasons an aggregate signature cannot be produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AggregationError {
+    /// The committee has no parties.
+    NoParties,
+
+    /// No party won a lottery, so there is nothing to aggregate.
+    NoSignatures,
+
+    /// The message is empty, so no certificate over it would verify.
+    EmptyMessage,
+
+    /// The message is `len` bytes long, more than the `max` that verification
+    /// accepts.
+    MessageTooLong { len: usize, max: usize },
+
+    /// The `signatures` collected from the lottery winners only hold
+    /// `indices` distinct winning indices, fewer than the `k` required.
+    NotEnoughSignatures {
+        signatures: usize,
+        indices: u64,
+        k: u64,
+    },
+
+    /// A committee of up to `max_parties` equal-stake parties still only won
+    /// `indices` distinct lottery indices, fewer than the `k` required.
+    QuorumUnreachable {
+        max_parties: usize,
+        indices: u64,
+        k: u64,
+    },
+
+    /// mithril-stm could not fit an index into a `usize`.
+    UsizeConversionInvalid,
+
+    /// The stakes of the parties add up to more than a `Stake` can hold.
+    StakeOverflow,
+}
+
+impl fmt::Display for AggregationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoParties => write!(f, "the committee has no parties"),
+            Self::NoSignatures => write!(f, "no party won a lottery"),
+            Self::EmptyMessage => write!(f, "message is empty"),
+            Self::MessageTooLong { len, max } => {
+                write!(
+                    f,
+                    "message is {len} bytes long, more than the {max} accepted"
+                )
+            }
+            Self::NotEnoughSignatures {
+                signatures,
+                indices,
+                k,
+            } => write!(
+                f,
+                "{signatures} signatures win {indices} distinct lottery indices, fewer than k = {k}"
+            ),
+            Self::QuorumUnreachable {
+                max_parties,
+                indices,
+                k,
+            } => write!(
+                f,
+                "{max_parties} parties win {indices} distinct lottery indices, fewer than k = {k}; \
+                 more parties do not help, raise phi_f or lower k"
+            ),
+            Self::UsizeConversionInvalid => write!(f, "lottery index does not fit in a usize"),
+            Self::StakeOverflow => write!(f, "total stake overflows"),
+        }
+    }
+}
+
+impl Error for AggregationError {}
+
+/// Reasons a set of externally provided keys cannot be registered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistrationError {
+    /// No keys were provided.
+    Empty,
+
+    /// The key at this position (counting from 0) was already registered by
+    /// an earlier entry.
+    DuplicateKey { index: usize },
+
+    /// The key at this position (counting from 0) fails its proof of
+    /// possession.
+    InvalidKey { index: usize },
+
+    /// The stakes of the parties add up to more than a `Stake` can hold.
+    StakeOverflow,
+
+    /// The key is not registered in the committee.
+    NotRegistered,
+
+    /// The Merkle tree over the parties does not decode into the tree of the
+    /// linked mithril-stm, whose layout it mirrors.
+    MerkleTree,
+}
+
+impl fmt::Display for RegistrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no keys to register"),
+            Self::DuplicateKey { index } => write!(f, "key {index} is already registered"),
+            Self::InvalidKey { index } => write!(f, "key {index} fails its proof of possession"),
+            Self::StakeOverflow => write!(f, "total stake overflows"),
+            Self::NotRegistered => write!(f, "key is not registered"),
+            Self::MerkleTree => write!(f, "cannot rebuild the Merkle tree"),
+        }
+    }
+}
+
+impl Error for RegistrationError {}
+
+/// Reasons a committee cannot be rebuilt from its packed keys and stakes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackedCommitteeError {
+    /// This many key words do not hold exactly one key per stake.
+    LengthMismatch { words: usize, stakes: usize },
+
+    /// The key at this position (counting from 0) does not decode.
+    InvalidKey { index: usize },
+
+    /// The keys and stakes do not form a valid registration.
+    Invalid(RegistrationError),
+}
+
+impl fmt::Display for PackedCommitteeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LengthMismatch { words, stakes } => {
+                write!(
+                    f,
+                    "{words} key words do not hold one key for each of {stakes} stakes"
+                )
+            }
+            Self::InvalidKey { index } => write!(f, "packed key {index} does not decode"),
+            Self::Invalid(_) => write!(f, "packed keys do not form a valid registration"),
+        }
+    }
+}
+
+impl Error for PackedCommitteeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Invalid(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<RegistrationError> for PackedCommitteeError {
+    fn from(err: RegistrationError) -> Self {
+        Self::Invalid(err)
+    }
+}
+
+/// Reasons a closed registration cannot be loaded from a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistrationFileError {
+    /// The file could not be read or written.
+    Io(std::io::ErrorKind),
+
+    /// The file does not hold a list of registered parties.
+    Malformed,
+
+    /// The parties listed in the file do not form a valid registration.
+    Invalid(RegistrationError),
+}
+
+impl fmt::Display for RegistrationFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(kind) => write!(f, "cannot access registration file: {kind}"),
+            Self::Malformed => write!(f, "registration file does not list registered parties"),
+            Self::Invalid(_) => write!(f, "registration file does not hold a valid registration"),
+        }
+    }
+}
+
+impl Error for RegistrationFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Invalid(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<RegistrationError> for RegistrationFileError {
+    fn from(err: RegistrationError) -> Self {
+        Self::Invalid(err)
+    }
+}
+
+/// Reasons a stake distribution cannot be loaded from a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StakeFileError {
+    /// The file could not be read.
+    Io(std::io::ErrorKind),
+
+    /// The CSV record on this line (counting from 1) is not a
+    /// `party_id,stake` pair with a non-negative integer stake.
+    InvalidRecord { line: usize },
+
+    /// The JSON file is not an array of non-negative integer stakes.
+    InvalidJson { reason: String },
+
+    /// The party at this position (counting from 0) holds no stake.
+    ZeroStake { party: usize },
+
+    /// The file lists no parties.
+    Empty,
+
+    /// The stakes add up to `total`, more than a `Stake` can hold.
+    StakeOverflow { total: u128 },
+}
+
+impl fmt::Display for StakeFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(kind) => write!(f, "cannot read stake file: {kind}"),
+            Self::InvalidRecord { line } => {
+                write!(f, "line {line} is not a `party_id,stake` record")
+            }
+            Self::InvalidJson { reason } => write!(f, "invalid stake JSON: {reason}"),
+            Self::ZeroStake { party } => write!(f, "party {party} holds no stake"),
+            Self::Empty => write!(f, "stake file lists no parties"),
+            Self::StakeOverflow { total } => {
+                write!(f, "stakes add up to {total}, more than a stake can hold")
+            }
+        }
+    }
+}
+
+impl Error for StakeFileError {}
+
+/// Reasons a byte string cannot be decoded into a certificate or an aggregate
+/// verification key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CertificateError {
+    /// The input ended before the certificate did.
+    Truncated,
+
+    /// The input holds this many bytes past the end of the certificate.
+    TrailingBytes(usize),
+
+    /// The input does not encode a certificate, e.g. it holds an invalid key
+    /// or signature.
+    Malformed,
+
+    /// The hex string has an odd length or holds a non-hex character.
+    InvalidHex,
+
+    /// The base64 string holds a character outside its alphabet or is not
+    /// correctly padded.
+    InvalidBase64,
+
+    /// The certificate decodes, but not into one produced under the expected
+    /// parameters by the expected committee.
+    StructureMismatch { reason: String },
+
+    /// The chunk at this index was delivered again with different contents.
+    ConflictingChunk(usize),
+
+    /// The chunk size is not a power of two of at most 32 bytes.
+    InvalidChunkSize(usize),
+
+    /// The chunked certificate would be this many bytes long, more than the
+    /// [MAX_CERTIFICATE_LEN](crate::MAX_CERTIFICATE_LEN) that is reassembled.
+    TooLong(usize),
+
+    /// A certificate of the stated length takes up `expected` chunks, but
+    /// `actual` chunks were delivered.
+    ChunkCountMismatch { expected: usize, actual: usize },
+
+    /// The certificate starts with this format version byte rather than the
+    /// [CERTIFICATE_VERSION](crate::CERTIFICATE_VERSION) that is decoded.
+    UnsupportedVersion(u8),
+}
+
+impl fmt::Display for CertificateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "input is truncated"),
+            Self::TrailingBytes(n) => write!(f, "input has {n} trailing bytes"),
+            Self::Malformed => write!(f, "input is malformed"),
+            Self::InvalidHex => write!(f, "input is not valid hex"),
+            Self::InvalidBase64 => write!(f, "input is not valid base64"),
+            Self::StructureMismatch { reason } => {
+                write!(
+                    f,
+                    "certificate does not match the expected committee: {reason}"
+                )
+            }
+            Self::ConflictingChunk(index) => {
+                write!(f, "chunk {index} was delivered with different contents")
+            }
+            Self::InvalidChunkSize(size) => write!(
+                f,
+                "chunk size {size} is not a power of two of at most 32 bytes"
+            ),
+            Self::TooLong(len) => write!(
+                f,
+                "certificate would be {len} bytes long, more than the {} reassembled",
+                crate::MAX_CERTIFICATE_LEN
+            ),
+            Self::ChunkCountMismatch { expected, actual } => write!(
+                f,
+                "certificate takes up {expected} chunks, but {actual} were delivered"
+            ),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "certificate format version {version} is not the supported {}",
+                crate::CERTIFICATE_VERSION
+            ),
+        }
+    }
+}
+
+impl Error for CertificateError {}
+
+/// Reasons a test vector file cannot be written or checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestVectorError {
+    /// The file could not be read or written.
+    Io(std::io::ErrorKind),
+
+    /// The file is not a JSON test vector.
+    InvalidJson { reason: String },
+
+    /// The stored certificate no longer verifies.
+    Verification(VerificationError),
+}
+
+impl fmt::Display for TestVectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(kind) => write!(f, "cannot access test vector file: {kind}"),
+            Self::InvalidJson { reason } => write!(f, "invalid test vector JSON: {reason}"),
+            Self::Verification(_) => write!(f, "stored certificate does not verify"),
+        }
+    }
+}
+
+impl Error for TestVectorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Verification(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Reasons an attestation is rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttestationError {
+    /// The attestation is about a different message.
+    MessageMismatch,
+
+    /// The signature does not decode or was not made by the trusted key.
+    InvalidSignature,
+}
+
+impl fmt::Display for AttestationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MessageMismatch => write!(f, "attestation is about a different message"),
+            Self::InvalidSignature => write!(f, "attestation is not signed by the trusted key"),
+        }
+    }
+}
+
+impl Error for AttestationError {}
+
+/// Reasons a committee snapshot cannot be imported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommitteeSnapshotError {
+    /// The value is not a committee snapshot.
+    InvalidJson { reason: String },
+
+    /// The verification key of the party at this position (counting from 0)
+    /// does not decode.
+    InvalidKey { index: usize },
+
+    /// The listed parties do not form a valid registration.
+    Invalid(RegistrationError),
+
+    /// The rebuilt registration does not have the recorded fingerprint.
+    FingerprintMismatch,
+}
+
+impl From<RegistrationError> for CommitteeSnapshotError {
+    fn from(err: RegistrationError) -> Self {
+        Self::Invalid(err)
+    }
+}
+
+impl fmt::Display for CommitteeSnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidJson { reason } => write!(f, "invalid committee snapshot: {reason}"),
+            Self::InvalidKey { index } => write!(f, "verification key {index} does not decode"),
+            Self::Invalid(_) => write!(f, "snapshot does not hold a valid registration"),
+            Self::FingerprintMismatch => {
+                write!(
+                    f,
+                    "rebuilt registration does not match the recorded fingerprint"
+                )
+            }
+        }
+    }
+}
+
+impl Error for CommitteeSnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Invalid(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Assert that each error displays a message containing its substring.
+    fn assert_displays<E: Error>(cases: &[(E, &str)]) {
+        for (err, expected) in cases {
+            let shown = err.to_string();
+            assert!(shown.contains(expected), "{shown:?} lacks {expected:?}");
+        }
+    }
+
+    #[test]
+    fn verification_errors_show_their_context() {
+        assert_displays(&[
+            (
+                VerificationError::ParameterMismatch {
+                    index: 3000,
+                    m: 2642,
+                },
+                "index 3000, which does not exist for m = 2642",
+            ),
+            (
+                VerificationError::BelowQuorum {
+                    indices: 10,
+                    k: 357,
+                },
+                "10 distinct lottery indices, fewer than k = 357",
+            ),
+            (
+                VerificationError::InvalidSignature {
+                    reason: "bad sigma".to_string(),
+                },
+                "bad sigma",
+            ),
+            (
+                VerificationError::Deserialization(CertificateError::Truncated),
+                "cannot decode certificate",
+            ),
+            (
+                VerificationError::AvkDeserialization(CertificateError::Truncated),
+                "cannot decode aggregate verification key",
+            ),
+            (VerificationError::EmptyMessage, "empty"),
+            (
+                VerificationError::MessageTooLong { len: 12, max: 10 },
+                "12 bytes long, more than the 10",
+            ),
+            (VerificationError::EmptyCommittee, "no party"),
+            (
+                VerificationError::RegistrationMismatch {
+                    expected: [0xaa; 32],
+                    actual: [0xbb; 32],
+                },
+                "registration 0xbbbb",
+            ),
+        ]);
+        let err = VerificationError::Deserialization(CertificateError::Truncated);
+        assert_eq!(err.source().unwrap().to_string(), "input is truncated");
+    }
+
+    #[test]
+    fn param_errors_show_their_context() {
+        assert_displays(&[
+            (ParamError::PhiFOutOfRange { phi_f: 1.5 }, "phi_f = 1.5"),
+            (ParamError::ZeroK, "k must be at least 1"),
+            (ParamError::ZeroM, "m must be at least 1"),
+            (ParamError::KExceedsM { k: 3, m: 2 }, "k = 3 exceeds m = 2"),
+            (
+                ParamError::InvalidEnv {
+                    var: "BONSAI_K",
+                    value: "ten".to_string(),
+                },
+                "BONSAI_K = \"ten\"",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn aggregation_errors_show_their_context() {
+        assert_displays(&[
+            (AggregationError::NoParties, "no parties"),
+            (AggregationError::NoSignatures, "no party won"),
+            (AggregationError::EmptyMessage, "empty"),
+            (
+                AggregationError::MessageTooLong { len: 12, max: 10 },
+                "12 bytes long",
+            ),
+            (
+                AggregationError::NotEnoughSignatures {
+                    signatures: 2,
+                    indices: 100,
+                    k: 357,
+                },
+                "2 signatures win 100 distinct lottery indices, fewer than k = 357",
+            ),
+            (
+                AggregationError::QuorumUnreachable {
+                    max_parties: 64,
+                    indices: 100,
+                    k: 357,
+                },
+                "64 parties win 100",
+            ),
+            (AggregationError::UsizeConversionInvalid, "usize"),
+            (AggregationError::StakeOverflow, "overflows"),
+        ]);
+    }
+
+    #[test]
+    fn registration_errors_show_their_context() {
+        assert_displays(&[
+            (RegistrationError::Empty, "no keys"),
+            (RegistrationError::DuplicateKey { index: 2 }, "key 2"),
+            (RegistrationError::InvalidKey { index: 3 }, "key 3"),
+            (RegistrationError::StakeOverflow, "overflows"),
+            (RegistrationError::NotRegistered, "not registered"),
+            (RegistrationError::MerkleTree, "Merkle tree"),
+        ]);
+    }
+}
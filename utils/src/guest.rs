@@ -0,0 +1,99 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Input read and journal committed by the STM verification guest program.
+
+use mithril_stm::stm::{StmAggrVerificationKey, StmParameters};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    deserialize_certificate, message_digest, serialize_avk, verify_aggregate_signature,
+    VerificationError, D,
+};
+
+/// Input of the guest: a message and the serialized certificate over it, as
+/// produced by [`crate::serialize_certificate`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuestInput {
+    /// The signed message.
+    pub msg: Vec<u8>,
+    /// The serialized aggregate signature over `msg`.
+    pub certificate: Vec<u8>,
+    /// Aggregate verification key of the signing committee.
+    pub avk: StmAggrVerificationKey<D>,
+}
+
+/// Journal committed by the guest once verification has run, with enough for
+/// an on-chain verifier to check that a specific committee verified a
+/// specific message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuestJournal {
+    /// [message_digest] of the message the certificate was checked against.
+    pub msg_digest: [u8; 32],
+    /// Digest of the [serialize_avk] encoding of the committee's aggregate
+    /// verification key, the same as its `avk_fingerprint` on the host.
+    pub avk_fingerprint: [u8; 32],
+    /// Whether the certificate deserialized and verified over the message.
+    pub verdict: bool,
+}
+
+impl GuestInput {
+    /// Deserialize the certificate and verify it over the message.
+    ///
+    /// Failures are reported in the verdict rather than by panicking, so the
+    /// guest always produces a journal.
+    pub fn verify(&self, params: StmParameters) -> GuestJournal {
+        let verdict = deserialize_certificate(&self.certificate)
+            .map_err(VerificationError::from)
+            .and_then(|msig| verify_aggregate_signature(&self.msg, &msig, &self.avk, params))
+            .is_ok();
+
+        GuestJournal {
+            msg_digest: message_digest(&self.msg),
+            avk_fingerprint: message_digest(&serialize_avk(&self.avk)),
+            verdict,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compute_avk_for_stake, default_params, generate_aggregate_signatures, serialize_certificate,
+    };
+
+    #[test]
+    fn journal_reports_the_verdict() {
+        let params = default_params();
+        let msg = b"guest input".to_vec();
+        let msig = generate_aggregate_signatures(&msg, params, 4).unwrap();
+        let mut input = GuestInput {
+            msg: msg.clone(),
+            certificate: serialize_certificate(&msig),
+            avk: compute_avk_for_stake(vec![1; 4], params),
+        };
+
+        let journal = input.verify(params);
+        assert!(journal.verdict);
+        assert_eq!(journal.msg_digest, message_digest(&msg));
+        assert_eq!(
+            journal.avk_fingerprint,
+            message_digest(&serialize_avk(&input.avk))
+        );
+
+        input.certificate.truncate(input.certificate.len() - 1);
+        assert!(!input.verify(params).verdict);
+    }
+}
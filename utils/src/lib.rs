@@ -0,0 +1,161 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Generation and verification of Mithril STM aggregate signatures.
+//!
+//! The functions in this crate have no side effects (they never print; they
+//! only emit `tracing` spans and events, which are dropped unless a subscriber
+//! is installed), so they can be shared between host tooling and the zkVM
+//! guest.
+//!
+//! Messages are opaque bytes everywhere: they are never decoded as UTF-8, and
+//! wherever they need a textual form, as in a [TestVector], they are written
+//! as hex.
+//!
+//! # Features
+//!
+//! - `host` (default): everything below, plus the Ethereum ABI encoding of
+//!   [VerificationData] and [digest_to_h256] (which pull in `ethabi`), the file
+//!   based [load_stake_distribution], [save_closed_reg], [load_closed_reg] and
+//!   [verify_test_vector], the timed [verify_with_report], the memoizing
+//!   [VerifierCache], the JSON committee snapshots of [export_committee] and
+//!   [import_committee] and the ECDSA [Attestation]s of [attest]. It is also
+//!   required by the `bonsai` command line tool.
+//! - `guest`: adds [GuestInput] and [GuestJournal], the input and journal of
+//!   the zkVM guest program.
+//! - `async`: adds [verify_aggregate_signature_async] for tokio services.
+//! - `wasm`: adds [verify_certificate_wasm], exported to JavaScript with
+//!   `wasm-bindgen`.
+//!
+//! Signing, including by remote parties, aggregation, (batch) verification,
+//! certificate (de)serialization and transport, parameter validation, lottery
+//! estimates and key registration are available in every configuration, so
+//! `cargo build --no-default-features --features guest` builds the crate for
+//! the zkVM guest without the host-only dependencies.
+
+use blake2::{digest::consts::U32, Blake2b};
+
+#[cfg(feature = "host")]
+mod abi;
+mod aggregator;
+#[cfg(feature = "host")]
+mod attestation;
+#[cfg(feature = "host")]
+mod cache;
+mod certificate;
+#[cfg(feature = "host")]
+mod committee;
+mod error;
+#[cfg(feature = "guest")]
+mod guest;
+mod lottery;
+mod params;
+mod registration;
+mod remote;
+#[cfg(feature = "host")]
+mod report;
+mod signing;
+#[cfg(feature = "host")]
+mod stake;
+#[cfg(feature = "host")]
+mod testvectors;
+mod transport;
+mod verification;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+#[cfg(feature = "guest")]
+pub use crate::guest::{GuestInput, GuestJournal};
+#[cfg(feature = "async")]
+pub use crate::verification::verify_aggregate_signature_async;
+#[cfg(feature = "wasm")]
+pub use crate::wasm::verify_certificate_wasm;
+#[cfg(feature = "host")]
+pub use crate::{
+    abi::{
+        avk_fingerprint, committee_root, digest_to_h256, FullVerificationData, VerificationData,
+    },
+    attestation::{attest, attestation_digest, verify_attestation, Attestation},
+    cache::VerifierCache,
+    committee::{export_committee, import_committee},
+    registration::{load_closed_reg, save_closed_reg},
+    report::{verify_with_report, VerificationReport},
+    stake::load_stake_distribution,
+    testvectors::{generate_test_vector, verify_test_vector, write_test_vector, TestVector},
+};
+pub use crate::{
+    aggregator::StreamingAggregator,
+    certificate::{
+        certificate_from_base64, certificate_from_hex, certificate_to_base64, certificate_to_hex,
+        deserialize_avk, deserialize_certificate, deserialize_certificate_checked,
+        estimate_certificate_size, participating_stake, serialize_avk, serialize_certificate,
+        signature_count, Base64Alphabet, CERTIFICATE_VERSION,
+    },
+    error::{
+        AggregationError, AttestationError, CertificateError, CommitteeSnapshotError,
+        PackedCommitteeError, ParamError, RegistrationError, RegistrationFileError, StakeFileError,
+        TestVectorError, VerificationError,
+    },
+    lottery::{expected_winners, simulate_winners},
+    params::{default_params, StmParametersExt},
+    registration::{
+        checked_total_stake, committee_from_packed, is_registered, pack_committee,
+        register_external_keys, rotate_committee, signer_stake, stake_table, sum_stake,
+        total_stake, CommitteeBuilder, KEY_WORDS,
+    },
+    remote::{SigningRequest, SigningResponse},
+    signing::{
+        aggregate_external, aggregate_signatures, compute_avk_for_stake, dedup_signatures,
+        domain_separated, find_signatures, find_signatures_with_indices,
+        generate_aggregate_from_subset, generate_aggregate_signatures,
+        generate_aggregate_signatures_for, generate_aggregate_signatures_for_stake,
+        generate_certificate_with_digest, generate_until_quorum, merge_aggregates, message_digest,
+        setup_committee, setup_committee_with_seed, setup_equal_parties,
+        setup_equal_parties_with_seed, setup_parties, setup_parties_with_seed, sign_digest,
+        sign_message, sign_with_domain, sign_with_indices, sign_with_mode, signer_verification_key,
+        try_compute_avk, try_compute_avk_for_reg, try_compute_avk_for_stake, winning_indices,
+        MessageMode, DEFAULT_DOMAIN, DEFAULT_SEED,
+    },
+    transport::{
+        chunk_certificate, estimate_chunks, reassemble_certificate, CertificateAssembler,
+        ChunkedCertificate, MAX_CERTIFICATE_LEN, WORD_SIZE,
+    },
+    verification::{
+        batch_verify, core_verify_sig, meets_quorum, verify_aggregate_signature,
+        verify_and_list_signers, verify_any, verify_digest, verify_single_sig, verify_stream,
+        verify_with_avk_bytes, verify_with_domain, verify_with_fallback, verify_with_mode,
+        verify_with_signing_avk, HashBackend, ParameterSet, Verifier, MAX_MESSAGE_LEN,
+    },
+};
+
+/// Hasher used throughout the crate unless a function is generic over it.
+pub type DefaultHasher = Blake2b<U32>;
+
+/// Hasher used for the Merkle tree batch proofs of an aggregate signature.
+pub type H = DefaultHasher;
+
+/// Hasher used for the key registration of the signing parties.
+pub type D = DefaultHasher;
+
+/// Stake held by a single party.
+pub type Stake = u64;
+
+// Compile-time check that the verification path stays reachable without the
+// host-only dependencies.
+#[cfg(all(feature = "guest", not(feature = "host")))]
+const _: () = {
+    let _ = GuestInput::verify;
+    let _ = deserialize_certificate;
+    let _ = verify_aggregate_signature::<DefaultHasher>;
+};
@@ -0,0 +1,90 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Estimates of how many parties win a lottery, for tuning `phi_f` and `k`
+//! without generating keys or signing.
+
+use rand_chacha::ChaCha20Rng;
+use rand_core::{RngCore, SeedableRng};
+
+use crate::Stake;
+
+/// Expected number of parties that win a single lottery.
+///
+/// A party holding the fraction `w` of the total stake wins each lottery with
+/// probability `1 - (1 - phi_f)^w`, so the expectation is the sum of that
+/// probability over all parties. Multiply by `m` for the expected number of
+/// lottery wins of a certificate.
+pub fn expected_winners(stake: &[Stake], phi_f: f64) -> f64 {
+    win_probabilities(stake, phi_f).sum()
+}
+
+/// Draw a single lottery for every party with the RNG seeded by `seed` and
+/// return how many win.
+///
+/// Averaged over many seeds this approaches [expected_winners].
+pub fn simulate_winners(stake: &[Stake], phi_f: f64, seed: [u8; 32]) -> usize {
+    let mut rng = ChaCha20Rng::from_seed(seed);
+    win_probabilities(stake, phi_f)
+        .filter(|&p| unit_sample(&mut rng) < p)
+        .count()
+}
+
+/// Probability of each party of `stake` winning a single lottery.
+fn win_probabilities(stake: &[Stake], phi_f: f64) -> impl Iterator<Item = f64> + '_ {
+    let total = stake.iter().map(|&s| s as f64).sum::<f64>();
+    stake.iter().map(move |&s| {
+        if s == 0 {
+            0.0
+        } else {
+            1.0 - (1.0 - phi_f).powf(s as f64 / total)
+        }
+    })
+}
+
+/// Uniform sample from `[0, 1)` built from the top 53 bits of `rng`.
+fn unit_sample(rng: &mut ChaCha20Rng) -> f64 {
+    (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_party_wins_with_probability_phi_f() {
+        assert!((expected_winners(&[7], 0.2) - 0.2).abs() < 1e-12);
+        assert_eq!(expected_winners(&[0, 5], 0.2), expected_winners(&[5], 0.2));
+        assert_eq!(expected_winners(&[1; 4], 0.0), 0.0);
+        assert_eq!(simulate_winners(&[1; 4], 1.0, [0; 32]), 4);
+    }
+
+    #[test]
+    fn simulation_trends_towards_the_expectation() {
+        let stake = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        let phi_f = 0.2;
+        let expected = expected_winners(&stake, phi_f);
+
+        let trials = 20_000u32;
+        let total: usize = (0..trials)
+            .map(|trial| {
+                let mut seed = [0; 32];
+                seed[..4].copy_from_slice(&trial.to_le_bytes());
+                simulate_winners(&stake, phi_f, seed)
+            })
+            .sum();
+        let mean = total as f64 / f64::from(trials);
+        assert!((mean - expected).abs() < 0.02, "{mean} vs {expected}");
+    }
+}
@@ -0,0 +1,718 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    error::Error,
+    fmt::{self, Debug, Display},
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+    process,
+    time::Instant,
+};
+
+use bonsai_experiment::{
+    certificate_from_base64, certificate_to_base64, certificate_to_hex, deserialize_avk,
+    deserialize_certificate, generate_aggregate_signatures_for_stake, load_closed_reg,
+    load_stake_distribution, message_digest, save_closed_reg, serialize_certificate,
+    setup_committee, try_compute_avk_for_reg, try_compute_avk_for_stake,
+    verify_aggregate_signature, verify_with_report, Base64Alphabet, CertificateError, Stake,
+    StmParametersExt, VerificationData, D, H,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use mithril_stm::stm::{StmAggrSig, StmAggrVerificationKey, StmParameters};
+use serde_json::json;
+use tracing::{debug, info};
+use tracing_subscriber::EnvFilter;
+
+/// Exit status of a certificate that does not verify, and of any failure
+/// other than malformed input.
+const EXIT_INVALID: i32 = 1;
+
+/// Exit status of an argument, file or stdin that cannot be parsed, as for
+/// the usage errors reported by clap.
+const EXIT_MALFORMED: i32 = 2;
+
+/// Message signed and verified by `selftest`.
+const SELFTEST_MESSAGE: &[u8] = b"bonsai selftest";
+
+/// Generate and verify Mithril STM certificates.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[command(flatten)]
+    params: ParamArgs,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Produce a certificate over a message and write it to stdout, as hex
+    /// unless `--format` says otherwise.
+    Generate {
+        #[command(flatten)]
+        message: MessageInput,
+
+        #[command(flatten)]
+        committee: CommitteeArgs,
+
+        /// Read a `-` message from stdin as raw bytes instead of hex.
+        #[arg(long)]
+        binary: bool,
+
+        /// Encoding of the certificate written to stdout.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Hex)]
+        format: OutputFormat,
+
+        /// Also write the closed registration of the committee to this file,
+        /// for `verify --registration`.
+        #[arg(long)]
+        registration_out: Option<PathBuf>,
+    },
+
+    /// Check a certificate over a message against the aggregate verification
+    /// key of its committee; exits with status 1 if it does not verify and 2
+    /// if an input is malformed.
+    Verify {
+        #[command(flatten)]
+        message: MessageInput,
+
+        /// The certificate, as printed by `generate`, or `-` to read it from
+        /// stdin.
+        #[arg(long)]
+        certificate: String,
+
+        /// Encoding of the certificate, as written by `generate --format`; a
+        /// `raw` certificate can only be read from stdin.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Hex)]
+        format: OutputFormat,
+
+        #[command(flatten)]
+        key: KeyArgs,
+
+        /// Print a JSON verification report to stdout.
+        #[arg(long)]
+        json: bool,
+
+        /// Read a `-` message or certificate from stdin as raw bytes instead
+        /// of hex.
+        #[arg(long)]
+        binary: bool,
+    },
+
+    /// Print the parameter set in use.
+    Params,
+
+    /// Generate a certificate and verify it again, printing the time each
+    /// step took; exits non-zero if any step fails.
+    Selftest {
+        #[command(flatten)]
+        committee: CommitteeArgs,
+    },
+}
+
+/// Encodings `generate` can write a certificate in, and `verify` read it in.
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// The serialized certificate bytes.
+    Raw,
+    /// `0x`-prefixed hex, as `verify` reads it.
+    Hex,
+    /// Standard padded base64.
+    Base64,
+    /// A JSON object with the hex certificate, the parameters and the
+    /// Blake2b-256 digest of the message.
+    Json,
+}
+
+/// The message to sign or verify, given inline or as a file.
+#[derive(Args)]
+struct MessageInput {
+    /// The message: `0x`-prefixed hex, text taken as its UTF-8 bytes, or `-`
+    /// to read it from stdin. Messages that are not valid UTF-8 must be given
+    /// as hex or with `--message-file`.
+    #[arg(
+        long,
+        conflicts_with = "message_file",
+        required_unless_present = "message_file"
+    )]
+    message: Option<String>,
+
+    /// File holding the raw bytes of the message.
+    #[arg(long)]
+    message_file: Option<PathBuf>,
+}
+
+/// Reasons a message or certificate argument does not resolve to its value.
+#[derive(Debug)]
+enum InputError {
+    /// Both an inline message and a message file were given.
+    Ambiguous,
+    /// Neither an inline message nor a message file was given.
+    Missing,
+    /// Both the message and the certificate were to be read from stdin.
+    StdinConflict,
+    /// The input starts with `0x`, or is read from stdin without `--binary`,
+    /// but is not valid hex.
+    InvalidHex,
+    /// The message file or stdin could not be read.
+    Io(io::ErrorKind),
+    /// A raw certificate was given as an argument rather than on stdin.
+    RawArgument,
+    /// The input is not a JSON object with a hex `certificate`, as written by
+    /// `generate --format json`.
+    InvalidJson,
+    /// The certificate bytes do not decode.
+    Certificate(CertificateError),
+    /// The aggregate verification key bytes do not decode.
+    Avk(CertificateError),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ambiguous => write!(f, "both --message and --message-file were given"),
+            Self::Missing => write!(f, "neither --message nor --message-file was given"),
+            Self::StdinConflict => write!(
+                f,
+                "the message and certificate cannot both be read from stdin"
+            ),
+            Self::InvalidHex => write!(f, "input is not valid hex"),
+            Self::Io(kind) => write!(f, "cannot read input: {kind}"),
+            Self::RawArgument => write!(f, "a raw certificate can only be read from stdin"),
+            Self::InvalidJson => write!(f, "input is not a JSON object with a hex certificate"),
+            Self::Certificate(err) => write!(f, "{err}"),
+            Self::Avk(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+/// Overrides of the default STM parameters, shared by all subcommands.
+#[derive(Args)]
+struct ParamArgs {
+    /// Number of lottery wins required for a valid certificate.
+    #[arg(long, global = true)]
+    k: Option<u64>,
+
+    /// Number of lotteries each party takes part in.
+    #[arg(long, global = true)]
+    m: Option<u64>,
+
+    /// Fraction of the total stake that is expected to win a lottery.
+    #[arg(long, global = true)]
+    phi_f: Option<f64>,
+}
+
+/// The signing committee, either equal-stake parties or a stake file.
+#[derive(Args)]
+struct CommitteeArgs {
+    /// Number of equal-stake parties in the signing committee [default: 4].
+    #[arg(long)]
+    parties: Option<usize>,
+
+    /// CSV (`party_id,stake`) or JSON file with the stake of each party.
+    #[arg(long, conflicts_with = "parties")]
+    stake_file: Option<PathBuf>,
+}
+
+/// The aggregate verification key of the committee that signed, which is all
+/// a verifier needs to know about it.
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct KeyArgs {
+    /// Hex of the serialized aggregate verification key of the committee.
+    #[arg(long)]
+    avk: Option<String>,
+
+    /// File holding the closed registration of the committee, as written by
+    /// `generate --registration-out`.
+    #[arg(long)]
+    registration: Option<PathBuf>,
+}
+
+impl MessageInput {
+    /// Resolve the message, reading a `-` message from `stdin` as raw bytes
+    /// if `binary` and as hex otherwise.
+    fn resolve(&self, stdin: impl Read, binary: bool) -> Result<Vec<u8>, InputError> {
+        match (&self.message, &self.message_file) {
+            (Some(_), Some(_)) => Err(InputError::Ambiguous),
+            (None, None) => Err(InputError::Missing),
+            (Some(message), None) if message == "-" => read_stdin(stdin, binary),
+            (Some(message), None) => match message.strip_prefix("0x") {
+                Some(digits) => hex::decode(digits).map_err(|_| InputError::InvalidHex),
+                None => Ok(message.as_bytes().to_vec()),
+            },
+            (None, Some(path)) => fs::read(path).map_err(|err| InputError::Io(err.kind())),
+        }
+    }
+}
+
+/// Stdin can only be read once, so it can hold the message or the
+/// certificate but not both.
+fn check_stdin_once(message: &MessageInput, certificate: &str) -> Result<(), InputError> {
+    if message.message.as_deref() == Some("-") && certificate == "-" {
+        return Err(InputError::StdinConflict);
+    }
+    Ok(())
+}
+
+/// Resolve a `--certificate` argument encoded in `format`, or `-` to read it
+/// from `stdin`.
+///
+/// Hex may come with or without a `0x` prefix, and text read from stdin may
+/// be surrounded by whitespace. As for [MessageInput::resolve], stdin is read
+/// as raw bytes with `binary`, whatever `format` says.
+fn resolve_certificate(
+    certificate: &str,
+    mut stdin: impl Read,
+    binary: bool,
+    format: OutputFormat,
+) -> Result<StmAggrSig<H>, InputError> {
+    let text = if certificate == "-" {
+        let mut bytes = Vec::new();
+        stdin
+            .read_to_end(&mut bytes)
+            .map_err(|err| InputError::Io(err.kind()))?;
+        if binary || matches!(format, OutputFormat::Raw) {
+            return deserialize_certificate(&bytes).map_err(InputError::Certificate);
+        }
+        // Anything that is not text fails to decode below.
+        String::from_utf8_lossy(&bytes).into_owned()
+    } else {
+        certificate.to_owned()
+    };
+    let text = text.trim();
+    let bytes = match format {
+        OutputFormat::Raw => return Err(InputError::RawArgument),
+        OutputFormat::Hex => decode_hex(text)?,
+        OutputFormat::Base64 => {
+            return certificate_from_base64(text, Base64Alphabet::Standard)
+                .map_err(InputError::Certificate)
+        }
+        OutputFormat::Json => {
+            let value: serde_json::Value =
+                serde_json::from_str(text).map_err(|_| InputError::InvalidJson)?;
+            let hex = value["certificate"].as_str();
+            decode_hex(hex.ok_or(InputError::InvalidJson)?)?
+        }
+    };
+    deserialize_certificate(&bytes).map_err(InputError::Certificate)
+}
+
+/// Read all of `stdin`, as raw bytes if `binary` and otherwise as hex with
+/// optional surrounding whitespace and `0x` prefix.
+fn read_stdin(mut stdin: impl Read, binary: bool) -> Result<Vec<u8>, InputError> {
+    let mut bytes = Vec::new();
+    stdin
+        .read_to_end(&mut bytes)
+        .map_err(|err| InputError::Io(err.kind()))?;
+    if binary {
+        return Ok(bytes);
+    }
+    let text = std::str::from_utf8(&bytes).map_err(|_| InputError::InvalidHex)?;
+    decode_hex(text.trim())
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, InputError> {
+    hex::decode(s.strip_prefix("0x").unwrap_or(s)).map_err(|_| InputError::InvalidHex)
+}
+
+impl ParamArgs {
+    /// Flags override the `BONSAI_*` environment variables, which override
+    /// the defaults.
+    fn resolve(&self) -> StmParameters {
+        let defaults = or_exit(
+            StmParameters::from_env_or_default(),
+            "invalid parameters",
+            EXIT_MALFORMED,
+        );
+        or_exit(
+            StmParameters::validated(
+                self.k.unwrap_or(defaults.k),
+                self.m.unwrap_or(defaults.m),
+                self.phi_f.unwrap_or(defaults.phi_f),
+            ),
+            "invalid parameters",
+            EXIT_MALFORMED,
+        )
+    }
+}
+
+impl CommitteeArgs {
+    fn resolve(&self) -> Vec<Stake> {
+        match &self.stake_file {
+            Some(path) => or_exit(
+                load_stake_distribution(path),
+                "failed to load stake distribution",
+                EXIT_MALFORMED,
+            ),
+            None => vec![1; self.parties.unwrap_or(4)],
+        }
+    }
+}
+
+impl KeyArgs {
+    fn resolve(&self) -> StmAggrVerificationKey<D> {
+        match (&self.avk, &self.registration) {
+            (Some(avk), _) => or_exit(
+                decode_hex(avk).and_then(|bytes| deserialize_avk(&bytes).map_err(InputError::Avk)),
+                "invalid aggregate verification key",
+                EXIT_MALFORMED,
+            ),
+            (None, Some(path)) => {
+                let reg = or_exit(
+                    load_closed_reg(path),
+                    "failed to load registration",
+                    EXIT_MALFORMED,
+                );
+                or_exit(
+                    try_compute_avk_for_reg(&reg),
+                    "invalid registration",
+                    EXIT_MALFORMED,
+                )
+            }
+            (None, None) => unreachable!("clap requires one of --avk and --registration"),
+        }
+    }
+}
+
+/// Unwrap `result`, or report its error to stderr as `what` and exit with
+/// `status`.
+///
+/// The error is printed rather than logged, so that it reaches stderr once
+/// whatever `RUST_LOG` says; the log only keeps it at `debug`.
+fn or_exit<T, E: Debug + Display>(result: Result<T, E>, what: &str, status: i32) -> T {
+    result.unwrap_or_else(|err| {
+        debug!(?err, "{what}");
+        eprintln!("{what}: {err}");
+        process::exit(status)
+    })
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // Log to stderr at `info` unless `RUST_LOG` says otherwise, keeping stdout
+    // for the command output.
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .try_init()
+        .map_err(|err| -> Box<dyn Error> { err })?;
+
+    let cli = Cli::parse();
+    let params = cli.params.resolve();
+
+    match cli.command {
+        Command::Generate {
+            message,
+            committee,
+            binary,
+            format,
+            registration_out,
+        } => {
+            let msg = or_exit(
+                message.resolve(io::stdin(), binary),
+                "invalid message",
+                EXIT_MALFORMED,
+            );
+            let stake = committee.resolve();
+            let msig = or_exit(
+                generate_aggregate_signatures_for_stake(&msg, params, stake.clone()),
+                "aggregation failed",
+                EXIT_INVALID,
+            );
+            if let Some(path) = registration_out {
+                // The stake was accepted for signing, so the same committee
+                // can be set up again without panicking.
+                let (_, reg) = setup_committee(params, stake);
+                or_exit(
+                    save_closed_reg(&reg, &path),
+                    "failed to save registration",
+                    EXIT_INVALID,
+                );
+            }
+            match format {
+                OutputFormat::Raw => io::stdout().write_all(&serialize_certificate(&msig))?,
+                OutputFormat::Hex => println!("{}", certificate_to_hex(&msig)),
+                OutputFormat::Base64 => {
+                    println!("{}", certificate_to_base64(&msig, Base64Alphabet::Standard))
+                }
+                OutputFormat::Json => {
+                    let output = json!({
+                        "certificate": certificate_to_hex(&msig),
+                        "params": params,
+                        "msg_digest": format!("0x{}", hex::encode(message_digest(&msg))),
+                    });
+                    println!("{output}");
+                }
+            }
+        }
+        Command::Verify {
+            message,
+            certificate,
+            format,
+            key,
+            json,
+            binary,
+        } => {
+            or_exit(
+                check_stdin_once(&message, &certificate),
+                "invalid input",
+                EXIT_MALFORMED,
+            );
+            let msg = or_exit(
+                message.resolve(io::stdin(), binary),
+                "invalid message",
+                EXIT_MALFORMED,
+            );
+            let msig = or_exit(
+                resolve_certificate(&certificate, io::stdin(), binary, format),
+                "invalid certificate",
+                EXIT_MALFORMED,
+            );
+            let avk = key.resolve();
+            if json {
+                let report = verify_with_report(&msg, &msig, &avk, params);
+                println!("{}", serde_json::to_string(&report)?);
+                if !report.verdict {
+                    process::exit(EXIT_INVALID);
+                }
+            } else {
+                or_exit(
+                    verify_aggregate_signature(&msg, &msig, &avk, params),
+                    "verification failed",
+                    EXIT_INVALID,
+                );
+
+                let data = VerificationData::new(&msg, &msig);
+                info!(msg = ?data.msg, msig = ?data.msig, "verification successful");
+            }
+        }
+        Command::Params => {
+            println!("k = {}", params.k);
+            println!("m = {}", params.m);
+            println!("phi_f = {}", params.phi_f);
+        }
+        Command::Selftest { committee } => {
+            let stake = committee.resolve();
+            let avk = or_exit(
+                try_compute_avk_for_stake(stake.clone(), params),
+                "invalid committee",
+                EXIT_MALFORMED,
+            );
+
+            let start = Instant::now();
+            let msig = or_exit(
+                generate_aggregate_signatures_for_stake(SELFTEST_MESSAGE, params, stake),
+                "selftest failed to generate",
+                EXIT_INVALID,
+            );
+            println!("generate: {:?}", start.elapsed());
+
+            let start = Instant::now();
+            let bytes = serialize_certificate(&msig);
+            let msig = or_exit(
+                deserialize_certificate(&bytes),
+                "selftest failed to deserialize",
+                EXIT_INVALID,
+            );
+            println!("serialize and deserialize: {:?}", start.elapsed());
+
+            let start = Instant::now();
+            let result = verify_aggregate_signature(SELFTEST_MESSAGE, &msig, &avk, params);
+            println!("verify: {:?}", start.elapsed());
+            let verdict = if result.is_ok() { "passed" } else { "failed" };
+            println!("selftest {verdict}");
+            or_exit(result, "selftest failed to verify", EXIT_INVALID);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io::Cursor};
+
+    use super::*;
+
+    fn input(message: Option<&str>, message_file: Option<PathBuf>) -> MessageInput {
+        MessageInput {
+            message: message.map(str::to_owned),
+            message_file,
+        }
+    }
+
+    fn resolve(input: &MessageInput) -> Result<Vec<u8>, InputError> {
+        input.resolve(Cursor::new(Vec::new()), false)
+    }
+
+    #[test]
+    fn inline_messages_resolve_as_hex_or_text() {
+        assert_eq!(
+            resolve(&input(Some("0xdeadbeef"), None)).unwrap(),
+            [0xde, 0xad, 0xbe, 0xef]
+        );
+        assert_eq!(
+            resolve(&input(Some("plain text"), None)).unwrap(),
+            b"plain text"
+        );
+        assert!(matches!(
+            resolve(&input(Some("0xzz"), None)),
+            Err(InputError::InvalidHex)
+        ));
+    }
+
+    #[test]
+    fn message_files_resolve_to_their_bytes() {
+        let path = env::temp_dir().join(format!("bonsai-message-{}", process::id()));
+        fs::write(&path, [0xff, 0x00, 0x80]).unwrap();
+        let resolved = resolve(&input(None, Some(path.clone())));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(resolved.unwrap(), [0xff, 0x00, 0x80]);
+
+        assert!(matches!(
+            resolve(&input(None, Some(path))),
+            Err(InputError::Io(io::ErrorKind::NotFound))
+        ));
+    }
+
+    #[test]
+    fn ambiguous_or_missing_messages_are_rejected() {
+        assert!(matches!(
+            resolve(&input(Some("text"), Some(PathBuf::from("file")))),
+            Err(InputError::Ambiguous)
+        ));
+        assert!(matches!(
+            resolve(&input(None, None)),
+            Err(InputError::Missing)
+        ));
+    }
+
+    #[test]
+    fn stdin_messages_are_hex_unless_binary() {
+        let stdin = input(Some("-"), None);
+        assert_eq!(
+            stdin
+                .resolve(Cursor::new(b" 0x0102\n".to_vec()), false)
+                .unwrap(),
+            [1, 2]
+        );
+        assert_eq!(
+            stdin
+                .resolve(Cursor::new(b"0x0102".to_vec()), true)
+                .unwrap(),
+            b"0x0102"
+        );
+    }
+
+    #[test]
+    fn stdin_holds_the_message_or_the_certificate_but_not_both() {
+        assert!(matches!(
+            check_stdin_once(&input(Some("-"), None), "-"),
+            Err(InputError::StdinConflict)
+        ));
+        assert!(check_stdin_once(&input(Some("-"), None), "0x01").is_ok());
+        assert!(check_stdin_once(&input(Some("text"), None), "-").is_ok());
+    }
+
+    #[test]
+    fn stdin_certificates_are_hex_unless_binary() {
+        let msig = generate_aggregate_signatures_for_stake(
+            b"stdin",
+            bonsai_experiment::default_params(),
+            vec![1; 4],
+        )
+        .unwrap();
+        let bytes = serialize_certificate(&msig);
+
+        let hex = format!("{}\n", certificate_to_hex(&msig));
+        let from_hex =
+            resolve_certificate("-", Cursor::new(hex.into_bytes()), false, OutputFormat::Hex)
+                .unwrap();
+        assert_eq!(serialize_certificate(&from_hex), bytes);
+
+        let from_raw =
+            resolve_certificate("-", Cursor::new(bytes.clone()), true, OutputFormat::Hex).unwrap();
+        assert_eq!(serialize_certificate(&from_raw), bytes);
+
+        assert!(matches!(
+            resolve_certificate("-", Cursor::new(bytes.clone()), false, OutputFormat::Hex),
+            Err(InputError::InvalidHex)
+        ));
+        assert!(matches!(
+            resolve_certificate(
+                "-",
+                Cursor::new(bytes[..10].to_vec()),
+                true,
+                OutputFormat::Hex
+            ),
+            Err(InputError::Certificate(CertificateError::Truncated))
+        ));
+    }
+
+    #[test]
+    fn certificates_resolve_in_every_generate_format() {
+        let msig = generate_aggregate_signatures_for_stake(
+            b"formats",
+            bonsai_experiment::default_params(),
+            vec![1; 4],
+        )
+        .unwrap();
+        let bytes = serialize_certificate(&msig);
+        let hex = certificate_to_hex(&msig);
+        let base64 = certificate_to_base64(&msig, Base64Alphabet::Standard);
+        let json = json!({ "certificate": hex, "params": {} }).to_string();
+
+        let resolve = |certificate: &str, stdin: &[u8], format| {
+            resolve_certificate(certificate, Cursor::new(stdin.to_vec()), false, format)
+                .map(|msig| serialize_certificate(&msig))
+        };
+        for (text, format) in [
+            (&hex, OutputFormat::Hex),
+            (&base64, OutputFormat::Base64),
+            (&json, OutputFormat::Json),
+        ] {
+            assert_eq!(resolve(text, b"", format).unwrap(), bytes);
+            let stdin = format!("{text}\n");
+            assert_eq!(resolve("-", stdin.as_bytes(), format).unwrap(), bytes);
+        }
+        assert_eq!(resolve("-", &bytes, OutputFormat::Raw).unwrap(), bytes);
+
+        assert!(matches!(
+            resolve("0x00", b"", OutputFormat::Raw),
+            Err(InputError::RawArgument)
+        ));
+        // Hex digits are base64 characters too, so hex only fails to decode
+        // as a certificate.
+        assert!(resolve(&hex, b"", OutputFormat::Base64).is_err());
+        assert!(matches!(
+            resolve("0x!", b"", OutputFormat::Base64),
+            Err(InputError::Certificate(CertificateError::InvalidBase64))
+        ));
+        assert!(matches!(
+            resolve(&hex, b"", OutputFormat::Json),
+            Err(InputError::InvalidJson)
+        ));
+        assert!(matches!(
+            resolve(r#"{"certificate": 1}"#, b"", OutputFormat::Json),
+            Err(InputError::InvalidJson)
+        ));
+        assert!(matches!(
+            resolve(&base64, b"", OutputFormat::Hex),
+            Err(InputError::InvalidHex)
+        ));
+    }
+}
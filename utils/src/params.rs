@@ -0,0 +1,221 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(feature = "host")]
+use std::{env, str::FromStr};
+
+use mithril_stm::stm::StmParameters;
+
+use crate::ParamError;
+
+/// The parameters shared by the signers and the verifier of a certificate.
+///
+/// Both sides must use the same values: a certificate produced under one
+/// parameter set does not verify under another.
+pub fn default_params() -> StmParameters {
+    StmParameters {
+        k: 357,
+        m: 2642,
+        phi_f: 0.2,
+    }
+}
+
+/// Construction of [StmParameters] that rejects combinations mithril-stm would
+/// accept but cannot produce or verify a certificate under.
+pub trait StmParametersExt: Sized {
+    /// Build parameters after checking that `0.0 <= phi_f <= 1.0`, that `k`
+    /// and `m` are positive and that `k <= m`.
+    fn validated(k: u64, m: u64, phi_f: f64) -> Result<Self, ParamError>;
+
+    /// Build parameters from the `BONSAI_K`, `BONSAI_M` and `BONSAI_PHI_F`
+    /// environment variables, taking any that is unset from [default_params],
+    /// and validate them like [StmParametersExt::validated].
+    #[cfg(feature = "host")]
+    fn from_env_or_default() -> Result<Self, ParamError>;
+}
+
+impl StmParametersExt for StmParameters {
+    fn validated(k: u64, m: u64, phi_f: f64) -> Result<Self, ParamError> {
+        if !(0.0..=1.0).contains(&phi_f) {
+            return Err(ParamError::PhiFOutOfRange { phi_f });
+        }
+        if k == 0 {
+            return Err(ParamError::ZeroK);
+        }
+        if m == 0 {
+            return Err(ParamError::ZeroM);
+        }
+        if k > m {
+            return Err(ParamError::KExceedsM { k, m });
+        }
+        Ok(Self { k, m, phi_f })
+    }
+
+    #[cfg(feature = "host")]
+    fn from_env_or_default() -> Result<Self, ParamError> {
+        let defaults = default_params();
+        Self::validated(
+            env_or("BONSAI_K", defaults.k)?,
+            env_or("BONSAI_M", defaults.m)?,
+            env_or("BONSAI_PHI_F", defaults.phi_f)?,
+        )
+    }
+}
+
+/// Parse the environment variable `var`, or fall back to `default` if it is
+/// unset.
+#[cfg(feature = "host")]
+fn env_or<T: FromStr>(var: &'static str, default: T) -> Result<T, ParamError> {
+    match env::var(var) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| ParamError::InvalidEnv { var, value }),
+        Err(env::VarError::NotPresent) => Ok(default),
+        Err(env::VarError::NotUnicode(value)) => Err(ParamError::InvalidEnv {
+            var,
+            value: value.to_string_lossy().into_owned(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_accepted() {
+        let defaults = default_params();
+        let params = StmParameters::validated(defaults.k, defaults.m, defaults.phi_f).unwrap();
+        assert_eq!((params.k, params.m, params.phi_f), (357, 2642, 0.2));
+    }
+
+    #[test]
+    fn invalid_combinations_are_rejected() {
+        let rejected = |k, m, phi_f| StmParameters::validated(k, m, phi_f).err();
+        assert_eq!(
+            rejected(1, 1, -0.1),
+            Some(ParamError::PhiFOutOfRange { phi_f: -0.1 })
+        );
+        assert_eq!(
+            rejected(1, 1, 1.5),
+            Some(ParamError::PhiFOutOfRange { phi_f: 1.5 })
+        );
+        assert!(matches!(
+            rejected(1, 1, f64::NAN),
+            Some(ParamError::PhiFOutOfRange { .. })
+        ));
+        assert_eq!(rejected(0, 1, 0.2), Some(ParamError::ZeroK));
+        assert_eq!(rejected(1, 0, 0.2), Some(ParamError::ZeroM));
+        assert_eq!(
+            rejected(3, 2, 0.2),
+            Some(ParamError::KExceedsM { k: 3, m: 2 })
+        );
+    }
+
+    /// Serializes the tests that touch the `BONSAI_*` variables.
+    #[cfg(feature = "host")]
+    static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    /// Sets environment variables for the lifetime of the guard, restoring
+    /// their previous values when dropped.
+    #[cfg(feature = "host")]
+    struct EnvGuard {
+        saved: Vec<(&'static str, Option<String>)>,
+        _lock: std::sync::MutexGuard<'static, ()>,
+    }
+
+    #[cfg(feature = "host")]
+    impl EnvGuard {
+        fn set(vars: &[(&'static str, Option<&str>)]) -> Self {
+            let lock = ENV_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+            let saved = vars
+                .iter()
+                .map(|&(var, value)| {
+                    let old = env::var(var).ok();
+                    match value {
+                        Some(value) => env::set_var(var, value),
+                        None => env::remove_var(var),
+                    }
+                    (var, old)
+                })
+                .collect();
+            Self { saved, _lock: lock }
+        }
+    }
+
+    #[cfg(feature = "host")]
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            for (var, old) in &self.saved {
+                match old {
+                    Some(value) => env::set_var(var, value),
+                    None => env::remove_var(var),
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "host")]
+    #[test]
+    fn absent_env_vars_fall_back_to_the_defaults() {
+        let _guard = EnvGuard::set(&[
+            ("BONSAI_K", None),
+            ("BONSAI_M", None),
+            ("BONSAI_PHI_F", None),
+        ]);
+        assert_eq!(StmParameters::from_env_or_default(), Ok(default_params()));
+    }
+
+    #[cfg(feature = "host")]
+    #[test]
+    fn present_env_vars_override_the_defaults() {
+        let _guard = EnvGuard::set(&[
+            ("BONSAI_K", Some("10")),
+            ("BONSAI_M", Some(" 100 ")),
+            ("BONSAI_PHI_F", None),
+        ]);
+        let params = StmParameters::from_env_or_default().unwrap();
+        assert_eq!((params.k, params.m, params.phi_f), (10, 100, 0.2));
+    }
+
+    #[cfg(feature = "host")]
+    #[test]
+    fn malformed_env_vars_are_rejected() {
+        {
+            let _guard = EnvGuard::set(&[
+                ("BONSAI_K", Some("ten")),
+                ("BONSAI_M", None),
+                ("BONSAI_PHI_F", None),
+            ]);
+            assert_eq!(
+                StmParameters::from_env_or_default(),
+                Err(ParamError::InvalidEnv {
+                    var: "BONSAI_K",
+                    value: "ten".to_string()
+                })
+            );
+        }
+
+        let _guard = EnvGuard::set(&[
+            ("BONSAI_K", Some("500")),
+            ("BONSAI_M", Some("100")),
+            ("BONSAI_PHI_F", None),
+        ]);
+        assert_eq!(
+            StmParameters::from_env_or_default(),
+            Err(ParamError::KExceedsM { k: 500, m: 100 })
+        );
+    }
+}
@@ -0,0 +1,531 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Key registration of committees whose keys were generated elsewhere, and
+//! persistence of closed registrations.
+
+use std::{collections::HashSet, sync::Arc};
+#[cfg(feature = "host")]
+use std::{fs, path::Path};
+
+use blake2::Digest;
+use mithril_stm::{
+    key_reg::{ClosedKeyReg, KeyReg, RegParty},
+    stm::{StmVerificationKey, StmVerificationKeyPoP},
+    RegisterError,
+};
+use serde::de::DeserializeOwned;
+
+#[cfg(feature = "host")]
+use crate::RegistrationFileError;
+use crate::{PackedCommitteeError, RegistrationError, Stake, D};
+
+/// Register the `(stake, key)` of every party in `entries` and close the
+/// registration, without holding any of their secret keys.
+///
+/// Each key carries the proof of possession produced by its owner's
+/// `StmInitializer`, which is checked before the key is accepted.
+pub fn register_external_keys(
+    entries: &[(Stake, StmVerificationKeyPoP)],
+) -> Result<ClosedKeyReg<D>, RegistrationError> {
+    let mut builder = CommitteeBuilder::new();
+    for &(stake, pk) in entries {
+        builder.add_party(stake, pk)?;
+    }
+    builder.finalize()
+}
+
+/// Key registration of a committee whose parties join one at a time, e.g. as
+/// they come online, before it is closed.
+///
+/// [CommitteeBuilder::finalize] consumes the builder, so no party can be added
+/// once the registration is closed.
+#[derive(Debug, Clone, Default)]
+pub struct CommitteeBuilder {
+    kr: KeyReg,
+    len: usize,
+    total_stake: Stake,
+}
+
+impl CommitteeBuilder {
+    /// Start a registration with no parties.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a party holding `stake` with the key `pk`, whose proof of
+    /// possession is checked first.
+    ///
+    /// Errors report the party by the number of parties added before it.
+    pub fn add_party(
+        &mut self,
+        stake: Stake,
+        pk: StmVerificationKeyPoP,
+    ) -> Result<(), RegistrationError> {
+        let index = self.len;
+        // Closing a registration whose stake overflows panics in mithril-stm.
+        let total_stake = self
+            .total_stake
+            .checked_add(stake)
+            .ok_or(RegistrationError::StakeOverflow)?;
+        self.kr.register(stake, pk).map_err(|err| match err {
+            RegisterError::KeyRegistered(_) => RegistrationError::DuplicateKey { index },
+            _ => RegistrationError::InvalidKey { index },
+        })?;
+        self.len += 1;
+        self.total_stake = total_stake;
+        Ok(())
+    }
+
+    /// Number of parties registered so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no party has been registered yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Sum of the stakes of the parties registered so far.
+    pub fn total_stake(&self) -> Stake {
+        self.total_stake
+    }
+
+    /// Close the registration.
+    pub fn finalize(self) -> Result<ClosedKeyReg<D>, RegistrationError> {
+        // Closing an empty registration panics in mithril-stm.
+        if self.is_empty() {
+            return Err(RegistrationError::Empty);
+        }
+        Ok(self.kr.close())
+    }
+}
+
+/// Verification key and stake of each party of `reg`, in the sorted order of
+/// its Merkle tree leaves.
+pub fn stake_table(reg: &ClosedKeyReg<D>) -> Vec<(StmVerificationKey, Stake)> {
+    reg.reg_parties
+        .iter()
+        .map(|party| (party.0, party.1))
+        .collect()
+}
+
+/// Whether `vk` is the verification key of a party of `reg`, e.g. to reject
+/// signatures from keys outside the committee before aggregation.
+pub fn is_registered(reg: &ClosedKeyReg<D>, vk: &StmVerificationKey) -> bool {
+    signer_stake(reg, vk).is_some()
+}
+
+/// Stake of the party of `reg` registered with `vk`, or `None` if `vk` is not
+/// part of the committee.
+pub fn signer_stake(reg: &ClosedKeyReg<D>, vk: &StmVerificationKey) -> Option<Stake> {
+    reg.reg_parties
+        .iter()
+        .find(|party| party.0 == *vk)
+        .map(|party| party.1)
+}
+
+/// Rebuild the committee of `reg` without the party registered with
+/// `revoke`, e.g. once its key is compromised.
+///
+/// The rebuilt registration has a different aggregate verification key, so
+/// certificates of the old committee no longer verify against it. Fails with
+/// [RegistrationError::NotRegistered] if `revoke` is not part of `reg`, and
+/// with [RegistrationError::Empty] if it was the only party.
+pub fn rotate_committee(
+    reg: &ClosedKeyReg<D>,
+    revoke: &StmVerificationKey,
+) -> Result<ClosedKeyReg<D>, RegistrationError> {
+    if !is_registered(reg, revoke) {
+        return Err(RegistrationError::NotRegistered);
+    }
+    let reg_parties = reg
+        .reg_parties
+        .iter()
+        .filter(|party| party.0 != *revoke)
+        .cloned()
+        .collect();
+    close_parties(reg_parties)
+}
+
+/// Sum of `stake`, which cannot overflow however large the committee and its
+/// stakes are.
+pub fn sum_stake(stake: &[Stake]) -> u128 {
+    stake.iter().map(|&s| u128::from(s)).sum()
+}
+
+/// Sum of `stake`, failing with [RegistrationError::StakeOverflow] if it does
+/// not fit in a [Stake], as mithril-stm requires of the total stake of a
+/// registration.
+pub fn checked_total_stake(stake: &[Stake]) -> Result<Stake, RegistrationError> {
+    Stake::try_from(sum_stake(stake)).map_err(|_| RegistrationError::StakeOverflow)
+}
+
+/// Sum of the stakes of the parties of `reg`.
+pub fn total_stake(reg: &ClosedKeyReg<D>) -> Stake {
+    reg.total_stake
+}
+
+/// Write the parties of `reg` to `path`, from which [load_closed_reg] can
+/// rebuild it.
+// A list of keys and stakes always encodes, so this never panics; an empty
+// file must not be written in place of a failure either.
+#[cfg(feature = "host")]
+#[allow(clippy::expect_used)]
+pub fn save_closed_reg(reg: &ClosedKeyReg<D>, path: &Path) -> Result<(), RegistrationFileError> {
+    let bytes = bincode::serialize(&reg.reg_parties).expect("registered parties always encode");
+    fs::write(path, bytes).map_err(|err| RegistrationFileError::Io(err.kind()))
+}
+
+/// Load a registration written by [save_closed_reg].
+///
+/// Only the parties are stored; the Merkle tree is rebuilt from them, so the
+/// loaded registration always commits to the parties it lists.
+#[cfg(feature = "host")]
+pub fn load_closed_reg(path: &Path) -> Result<ClosedKeyReg<D>, RegistrationFileError> {
+    let bytes = fs::read(path).map_err(|err| RegistrationFileError::Io(err.kind()))?;
+    let reg_parties: Vec<RegParty> =
+        bincode::deserialize(&bytes).map_err(|_| RegistrationFileError::Malformed)?;
+    Ok(close_parties(reg_parties)?)
+}
+
+/// Number of `bytes32` words a packed verification key occupies.
+pub const KEY_WORDS: usize = 3;
+
+/// Pack the verification keys of `reg` into `bytes32` words, [KEY_WORDS] per
+/// key, alongside their stakes, as a contract stores the committee.
+///
+/// Parties are listed in the sorted order of the Merkle tree leaves;
+/// [committee_from_packed] rebuilds the registration.
+pub fn pack_committee(reg: &ClosedKeyReg<D>) -> (Vec<[u8; 32]>, Vec<Stake>) {
+    let mut keys = Vec::with_capacity(KEY_WORDS * reg.reg_parties.len());
+    for party in &reg.reg_parties {
+        for chunk in party.0.to_bytes().chunks(32) {
+            let mut word = [0u8; 32];
+            word.copy_from_slice(chunk);
+            keys.push(word);
+        }
+    }
+    let stakes = reg.reg_parties.iter().map(|party| party.1).collect();
+    (keys, stakes)
+}
+
+/// Rebuild a registration from verification keys packed into `bytes32`
+/// words, [KEY_WORDS] per key, and the stake of each key, as written by
+/// [pack_committee], e.g. to verify locally against a committee read from
+/// contract storage.
+///
+/// The keys carry no proof of possession, so they must come from a source
+/// that checked it at registration.
+pub fn committee_from_packed(
+    keys: &[[u8; 32]],
+    stakes: &[Stake],
+) -> Result<ClosedKeyReg<D>, PackedCommitteeError> {
+    if keys.len() != KEY_WORDS * stakes.len() {
+        return Err(PackedCommitteeError::LengthMismatch {
+            words: keys.len(),
+            stakes: stakes.len(),
+        });
+    }
+    let reg_parties = keys
+        .chunks(KEY_WORDS)
+        .zip(stakes)
+        .enumerate()
+        .map(|(index, (words, &stake))| {
+            StmVerificationKey::from_bytes(&words.concat())
+                .map(|vk| reg_party(vk, stake))
+                .map_err(|_| PackedCommitteeError::InvalidKey { index })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(close_parties(reg_parties)?)
+}
+
+/// The registered party holding `vk` and `stake`.
+// mithril-stm does not export the tuple struct `RegParty` aliases, so its
+// fields can only be initialized by number.
+#[allow(clippy::init_numbered_fields)]
+pub(crate) fn reg_party(vk: StmVerificationKey, stake: Stake) -> RegParty {
+    RegParty { 0: vk, 1: stake }
+}
+
+/// Close a registration of already verified `reg_parties`, producing the same
+/// [ClosedKeyReg] as `KeyReg::close` would have had they been registered one
+/// by one.
+pub(crate) fn close_parties(
+    mut reg_parties: Vec<RegParty>,
+) -> Result<ClosedKeyReg<D>, RegistrationError> {
+    // Building the Merkle tree of an empty registration panics in mithril-stm.
+    if reg_parties.is_empty() {
+        return Err(RegistrationError::Empty);
+    }
+
+    let mut keys = HashSet::new();
+    let mut total_stake: Stake = 0;
+    for (index, party) in reg_parties.iter().enumerate() {
+        if !keys.insert(party.0) {
+            return Err(RegistrationError::DuplicateKey { index });
+        }
+        total_stake = total_stake
+            .checked_add(party.1)
+            .ok_or(RegistrationError::StakeOverflow)?;
+    }
+    reg_parties.sort();
+
+    Ok(ClosedKeyReg {
+        merkle_tree: Arc::new(merkle_tree(&reg_parties)?),
+        reg_parties,
+        total_stake,
+    })
+}
+
+/// Build the Merkle tree over the sorted `leaves` as mithril-stm does.
+///
+/// mithril-stm does not export its `MerkleTree` type, so `T` is inferred from
+/// the field the tree is stored in and the nodes are decoded into it through
+/// its serde representation. Fails with [RegistrationError::MerkleTree] if
+/// that representation is not the `(nodes, leaf_off, n)` of mithril-stm
+/// 0.3.1, which the crate is pinned to.
+fn merkle_tree<T: DeserializeOwned>(leaves: &[RegParty]) -> Result<T, RegistrationError> {
+    let n = leaves.len();
+    let num_nodes = n + n.next_power_of_two() - 1;
+    let leaf_off = num_nodes - n;
+
+    let mut nodes = vec![vec![0u8]; num_nodes];
+    for (node, leaf) in nodes[leaf_off..].iter_mut().zip(leaves) {
+        let mut bytes = leaf.0.to_bytes().to_vec();
+        bytes.extend_from_slice(&leaf.1.to_be_bytes());
+        *node = D::digest(bytes).to_vec();
+    }
+
+    let empty = D::digest([0u8]).to_vec();
+    for i in (0..leaf_off).rev() {
+        let left = nodes.get(2 * i + 1).unwrap_or(&empty);
+        let right = nodes.get(2 * i + 2).unwrap_or(&empty);
+        nodes[i] = D::new()
+            .chain_update(left)
+            .chain_update(right)
+            .finalize()
+            .to_vec();
+    }
+
+    bincode::serialize(&(nodes, leaf_off, n))
+        .and_then(|bytes| bincode::deserialize(&bytes))
+        .map_err(|_| RegistrationError::MerkleTree)
+}
+
+#[cfg(test)]
+mod tests {
+    use mithril_stm::stm::StmInitializer;
+    use rand_chacha::ChaCha20Rng;
+    use rand_core::SeedableRng;
+
+    use super::*;
+    use crate::{
+        default_params, setup_committee, sign_with_mode, try_compute_avk_for_reg,
+        verify_aggregate_signature, MessageMode,
+    };
+
+    /// Proofs of possession of `n` freshly generated keys.
+    fn keys(n: usize) -> Vec<StmVerificationKeyPoP> {
+        let mut rng = ChaCha20Rng::from_seed([7; 32]);
+        (0..n)
+            .map(|_| StmInitializer::setup(default_params(), 1, &mut rng).verification_key())
+            .collect()
+    }
+
+    #[test]
+    fn duplicate_external_key_is_rejected() {
+        let keys = keys(2);
+        let reg = register_external_keys(&[(1, keys[0]), (2, keys[1])]).unwrap();
+        assert_eq!(reg.reg_parties.len(), 2);
+        assert_eq!(total_stake(&reg), 3);
+
+        let duplicate = register_external_keys(&[(1, keys[0]), (2, keys[1]), (3, keys[0])]);
+        assert_eq!(
+            duplicate.err(),
+            Some(RegistrationError::DuplicateKey { index: 2 })
+        );
+    }
+
+    #[cfg(feature = "host")]
+    #[test]
+    fn saved_registration_reloads_with_the_same_avk() {
+        use crate::avk_fingerprint;
+
+        let mut kr = KeyReg::init();
+        for (stake, pk) in [3, 1, 2].into_iter().zip(keys(3)) {
+            kr.register(stake, pk).unwrap();
+        }
+        let reg = kr.close();
+        let path = std::env::temp_dir().join(format!("bonsai-reg-{}.bin", std::process::id()));
+
+        save_closed_reg(&reg, &path).unwrap();
+        let loaded = load_closed_reg(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(
+            avk_fingerprint(&try_compute_avk_for_reg(&loaded).unwrap()),
+            avk_fingerprint(&try_compute_avk_for_reg(&reg).unwrap())
+        );
+        assert_eq!(total_stake(&loaded), 6);
+    }
+
+    #[test]
+    fn committee_built_in_steps_verifies_its_certificates() {
+        let params = default_params();
+        let mut rng = ChaCha20Rng::from_seed([7; 32]);
+        let initializers: Vec<StmInitializer> = (0..3)
+            .map(|_| StmInitializer::setup(params, 1, &mut rng))
+            .collect();
+
+        let mut builder = CommitteeBuilder::new();
+        for (step, initializer) in initializers.iter().enumerate() {
+            builder
+                .add_party(1, initializer.verification_key())
+                .unwrap();
+            assert_eq!(builder.len(), step + 1);
+            assert_eq!(builder.total_stake(), step as Stake + 1);
+        }
+        assert_eq!(
+            builder.add_party(1, initializers[0].verification_key()),
+            Err(RegistrationError::DuplicateKey { index: 3 })
+        );
+        let reg = builder.finalize().unwrap();
+
+        let msg = b"committee built in steps";
+        let ps: Vec<_> = initializers
+            .into_iter()
+            .map(|initializer| initializer.new_signer(reg.clone()).unwrap())
+            .collect();
+        let msig = sign_with_mode(MessageMode::Raw, msg, &ps).unwrap();
+        let avk = try_compute_avk_for_reg(&reg).unwrap();
+        verify_aggregate_signature(msg, &msig, &avk, params).unwrap();
+    }
+
+    #[test]
+    fn stake_table_of_an_equal_stake_committee() {
+        let (ps, reg) = setup_committee(default_params(), vec![1; 4]);
+        assert_eq!(total_stake(&reg), 4);
+
+        let table = stake_table(&reg);
+        assert_eq!(table.len(), 4);
+        assert!(table.iter().all(|&(_, stake)| stake == 1));
+        for party in &ps {
+            assert!(table.iter().any(|(vk, _)| *vk == party.verification_key()));
+        }
+    }
+
+    #[test]
+    fn only_registered_keys_have_a_stake() {
+        let keys = keys(2);
+        let reg = register_external_keys(&[(5, keys[0])]).unwrap();
+
+        assert!(is_registered(&reg, &keys[0].vk));
+        assert_eq!(signer_stake(&reg, &keys[0].vk), Some(5));
+        assert!(!is_registered(&reg, &keys[1].vk));
+        assert_eq!(signer_stake(&reg, &keys[1].vk), None);
+    }
+
+    #[cfg(feature = "host")]
+    #[test]
+    fn packed_committee_rebuilds_the_closed_registration() {
+        use crate::avk_fingerprint;
+
+        let mut kr = KeyReg::init();
+        for (stake, pk) in [4, 1, 3].into_iter().zip(keys(3)) {
+            kr.register(stake, pk).unwrap();
+        }
+        let reg = kr.close();
+
+        let (words, stakes) = pack_committee(&reg);
+        assert_eq!(words.len(), KEY_WORDS * 3);
+        let rebuilt = committee_from_packed(&words, &stakes).unwrap();
+
+        assert_eq!(
+            bincode::serialize(&*rebuilt.merkle_tree).unwrap(),
+            bincode::serialize(&*reg.merkle_tree).unwrap()
+        );
+        assert_eq!(
+            avk_fingerprint(&try_compute_avk_for_reg(&rebuilt).unwrap()),
+            avk_fingerprint(&try_compute_avk_for_reg(&reg).unwrap())
+        );
+        assert_eq!(
+            committee_from_packed(&words[1..], &stakes).err(),
+            Some(PackedCommitteeError::LengthMismatch {
+                words: 8,
+                stakes: 3
+            })
+        );
+    }
+
+    #[cfg(feature = "host")]
+    #[test]
+    fn rotated_committee_drops_the_revoked_key() {
+        use crate::avk_fingerprint;
+
+        let params = default_params();
+        let mut rng = ChaCha20Rng::from_seed([7; 32]);
+        let initializers: Vec<StmInitializer> = (0..4)
+            .map(|_| StmInitializer::setup(params, 1, &mut rng))
+            .collect();
+        let close = |skip: Option<usize>| {
+            let mut kr = KeyReg::init();
+            for (i, initializer) in initializers.iter().enumerate() {
+                if Some(i) != skip {
+                    kr.register(1, initializer.verification_key()).unwrap();
+                }
+            }
+            kr.close()
+        };
+        let reg = close(None);
+        let revoked = initializers[1].verification_key().vk;
+
+        let rotated = rotate_committee(&reg, &revoked).unwrap();
+        assert!(!is_registered(&rotated, &revoked));
+        let fingerprint = |reg| avk_fingerprint(&try_compute_avk_for_reg(reg).unwrap());
+        let closed = close(Some(1));
+        assert_eq!(fingerprint(&rotated), fingerprint(&closed));
+        assert_ne!(fingerprint(&rotated), fingerprint(&reg));
+
+        let msg = b"before rotation";
+        let ps: Vec<_> = initializers
+            .into_iter()
+            .map(|initializer| initializer.new_signer(reg.clone()).unwrap())
+            .collect();
+        let msig = sign_with_mode(MessageMode::Raw, msg, &ps).unwrap();
+        let old_avk = try_compute_avk_for_reg(&reg).unwrap();
+        let new_avk = try_compute_avk_for_reg(&rotated).unwrap();
+        verify_aggregate_signature(msg, &msig, &old_avk, params).unwrap();
+        assert!(verify_aggregate_signature(msg, &msig, &new_avk, params).is_err());
+
+        assert_eq!(
+            rotate_committee(&rotated, &revoked).err(),
+            Some(RegistrationError::NotRegistered)
+        );
+    }
+
+    #[test]
+    fn stakes_beyond_u64_are_summed_exactly() {
+        let stake = [Stake::MAX, Stake::MAX, 2];
+        assert_eq!(sum_stake(&stake), 2 * u128::from(Stake::MAX) + 2);
+        assert_eq!(
+            checked_total_stake(&stake),
+            Err(RegistrationError::StakeOverflow)
+        );
+        assert_eq!(checked_total_stake(&[Stake::MAX - 1, 1]), Ok(Stake::MAX));
+        assert_eq!(sum_stake(&[]), 0);
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Messages exchanged with signers that run in separate processes.
+//!
+//! A coordinator sends each party a [SigningRequest], the party answers with a
+//! [SigningResponse] produced by [SigningRequest::sign], and the coordinator
+//! collects the decoded signatures for
+//! [aggregate_external](crate::aggregate_external).
+
+use mithril_stm::stm::{StmSig, StmSigner};
+use serde::{Deserialize, Serialize};
+
+use crate::{certificate::decode_exact, domain_separated, sign_message, CertificateError, D};
+
+/// Ask a party to sign `msg` bound to `domain`, as
+/// [sign_with_domain](crate::sign_with_domain) does for local signers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SigningRequest {
+    pub msg: Vec<u8>,
+    pub domain: Vec<u8>,
+}
+
+impl SigningRequest {
+    /// Answer the request with the signature of `signer`, if it wins at least
+    /// one lottery.
+    // A signature only holds byte arrays, integers and sequences of known
+    // length, which bincode always encodes, so this never panics; an empty
+    // signature must not be sent in place of a failure either.
+    #[allow(clippy::expect_used)]
+    pub fn sign(&self, signer: &StmSigner<D>) -> SigningResponse {
+        let sig = sign_message(signer, &self.signed_message());
+        SigningResponse {
+            sig: sig.map(|sig| bincode::serialize(&sig).expect("signatures always encode")),
+        }
+    }
+
+    /// The bytes the parties sign, and that the aggregate is built and
+    /// verified over.
+    pub fn signed_message(&self) -> Vec<u8> {
+        domain_separated(&self.domain, &self.msg)
+    }
+}
+
+/// The answer of a party to a [SigningRequest]: its encoded signature, or
+/// `None` if it lost every lottery.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SigningResponse {
+    pub sig: Option<Vec<u8>>,
+}
+
+impl SigningResponse {
+    /// Decode the signature carried by the response.
+    ///
+    /// The response comes from another process, so bytes that do not hold
+    /// exactly one signature are rejected as for
+    /// [deserialize_certificate](crate::deserialize_certificate).
+    pub fn signature(&self) -> Result<Option<StmSig>, CertificateError> {
+        self.sig.as_deref().map(decode_exact).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aggregate_external, default_params, setup_committee, try_compute_avk_for_reg,
+        verify_with_domain, DEFAULT_DOMAIN,
+    };
+
+    /// Send `value` across a process boundary and back.
+    fn over_the_wire<T: Serialize + for<'de> Deserialize<'de>>(value: &T) -> T {
+        bincode::deserialize(&bincode::serialize(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn responses_of_two_remote_signers_aggregate_centrally() {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 2]);
+        let request = SigningRequest {
+            msg: b"remote signing".to_vec(),
+            domain: DEFAULT_DOMAIN.to_vec(),
+        };
+
+        // Each signer answers the request in its own process.
+        let sigs: Vec<StmSig> = ps
+            .iter()
+            .map(|signer| over_the_wire(&over_the_wire(&request).sign(signer)))
+            .map(|response| response.signature().unwrap().unwrap())
+            .collect();
+        assert_eq!(sigs.len(), 2);
+
+        let msig = aggregate_external(&reg, &sigs, &request.signed_message(), params).unwrap();
+        let avk = try_compute_avk_for_reg(&reg).unwrap();
+        assert_eq!(
+            verify_with_domain(DEFAULT_DOMAIN, &request.msg, &msig, &avk, params),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn malformed_responses_are_rejected() {
+        let mut response = SigningResponse { sig: None };
+        assert!(matches!(response.signature(), Ok(None)));
+
+        response.sig = Some(vec![1, 2, 3]);
+        assert!(response.signature().is_err());
+    }
+}
@@ -0,0 +1,85 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Structured outcome of a verification, for dashboards and other tooling.
+
+use std::time::Instant;
+
+use mithril_stm::stm::{StmAggrSig, StmAggrVerificationKey, StmParameters};
+use serde::{Deserialize, Serialize};
+
+use crate::{signature_count, verify_aggregate_signature, D, H};
+
+/// Outcome of [verify_with_report].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VerificationReport {
+    /// Whether the certificate verified.
+    pub verdict: bool,
+    /// Why the certificate was rejected, if it was.
+    pub reason: Option<String>,
+    /// The parameters the certificate was checked against.
+    pub params: StmParameters,
+    /// Number of party signatures in the certificate, one per signer.
+    /// mithril-stm keeps only as many as the quorum needs, so this can be
+    /// fewer than the parties that won a lottery, let alone the committee.
+    pub n_signatures: usize,
+    /// Wall-clock time spent verifying, in milliseconds.
+    pub elapsed_ms: u128,
+}
+
+/// Run [verify_aggregate_signature] and describe its outcome.
+pub fn verify_with_report(
+    msg: &[u8],
+    msig: &StmAggrSig<H>,
+    avk: &StmAggrVerificationKey<D>,
+    params: StmParameters,
+) -> VerificationReport {
+    let start = Instant::now();
+    let result = verify_aggregate_signature(msg, msig, avk, params);
+    let elapsed_ms = start.elapsed().as_millis();
+
+    VerificationReport {
+        verdict: result.is_ok(),
+        reason: result.err().map(|err| err.to_string()),
+        params,
+        n_signatures: signature_count(msig),
+        elapsed_ms,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compute_avk_for_stake, default_params, generate_aggregate_signatures};
+
+    #[test]
+    fn report_matches_the_verification_result() {
+        let params = default_params();
+        let msg = b"report";
+        let msig = generate_aggregate_signatures(msg, params, 4).unwrap();
+        let avk = compute_avk_for_stake(vec![1; 4], params);
+
+        let report = verify_with_report(msg, &msig, &avk, params);
+        assert!(report.verdict);
+        assert_eq!(report.reason, None);
+        assert_eq!(report.n_signatures, signature_count(&msig));
+        assert!(report.n_signatures <= 4);
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json["elapsed_ms"].is_u64());
+
+        let report = verify_with_report(b"another message", &msig, &avk, params);
+        assert!(!report.verdict);
+        assert!(report.reason.is_some());
+    }
+}
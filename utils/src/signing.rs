@@ -0,0 +1,1141 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeSet;
+
+use blake2::digest::{Digest, FixedOutput};
+use mithril_stm::{
+    key_reg::{ClosedKeyReg, KeyReg},
+    stm::{
+        StmAggrSig, StmAggrVerificationKey, StmClerk, StmInitializer, StmParameters, StmSig,
+        StmSigner, StmVerificationKey,
+    },
+};
+use rand_chacha::ChaCha20Rng;
+use rand_core::SeedableRng;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use serde::Serialize;
+use tracing::{debug, instrument};
+
+use crate::{
+    certificate::{encode, encode_versioned, signatures},
+    checked_total_stake,
+    verification::check_message,
+    AggregationError, DefaultHasher, RegistrationError, Stake, VerificationError, D, H,
+};
+
+/// Domain tag for [sign_with_domain] when a protocol has no tag of its own.
+pub const DEFAULT_DOMAIN: &[u8] = b"MITHRIL-V1";
+
+/// Seed of the key material generated by [setup_parties] and
+/// [setup_equal_parties].
+///
+/// Party `i` draws its keys from stream `i` of a ChaCha20 RNG seeded with this
+/// value, so its keys depend neither on the other parties nor on the order in
+/// which they are set up. They differ from the keys obtained by drawing every
+/// party in turn from a single stream of the same seed.
+pub const DEFAULT_SEED: [u8; 32] = [0u8; 32];
+
+/// Set up `nparties` signers that each hold a stake of 1, with key material
+/// derived from [DEFAULT_SEED].
+pub fn setup_equal_parties(params: StmParameters, nparties: usize) -> Vec<StmSigner<D>> {
+    setup_equal_parties_with_seed(params, nparties, DEFAULT_SEED)
+}
+
+/// Set up `nparties` signers that each hold a stake of 1, with key material
+/// derived from `seed`.
+pub fn setup_equal_parties_with_seed(
+    params: StmParameters,
+    nparties: usize,
+    seed: [u8; 32],
+) -> Vec<StmSigner<D>> {
+    let stake = vec![1; nparties];
+    setup_parties_with_seed(params, stake, seed)
+}
+
+/// Set up one signer per entry of `stake`, register all of them and close the
+/// registration. With the `rayon` feature the key material is generated in
+/// parallel, yielding the same signers.
+///
+/// Key material is derived from [DEFAULT_SEED], so the same stake distribution
+/// always yields the same signers. Use [setup_committee] to also get the
+/// closed registration, e.g. for its key with [try_compute_avk_for_reg].
+pub fn setup_parties(params: StmParameters, stake: Vec<Stake>) -> Vec<StmSigner<D>> {
+    setup_parties_with_seed(params, stake, DEFAULT_SEED)
+}
+
+/// Like [setup_parties], but with key material derived from `seed`, so that
+/// distinct seeds yield independent signer sets.
+pub fn setup_parties_with_seed(
+    params: StmParameters,
+    stake: Vec<Stake>,
+    seed: [u8; 32],
+) -> Vec<StmSigner<D>> {
+    setup_committee_with_seed(params, stake, seed).0
+}
+
+/// Like [setup_parties], but also return the closed registration the signers
+/// were built from, e.g. to inspect the committee behind a certificate.
+pub fn setup_committee(
+    params: StmParameters,
+    stake: Vec<Stake>,
+) -> (Vec<StmSigner<D>>, ClosedKeyReg<D>) {
+    setup_committee_with_seed(params, stake, DEFAULT_SEED)
+}
+
+/// Like [setup_committee], but with key material derived from `seed`.
+///
+/// Panics, as mithril-stm does when closing the registration, if `stake` is
+/// empty or adds up to more than a [Stake] can hold, which
+/// [checked_total_stake] reports instead.
+// Building a signer from its own initializer and the registration of its
+// key never fails. mithril-stm takes the registration by value, so each
+// signer gets its own copy of the parties; the Merkle tree is shared.
+#[allow(clippy::unwrap_used)]
+pub fn setup_committee_with_seed(
+    params: StmParameters,
+    stake: Vec<Stake>,
+    seed: [u8; 32],
+) -> (Vec<StmSigner<D>>, ClosedKeyReg<D>) {
+    let (ps, kr) = setup_initializers(params, &stake, seed);
+    let closed_reg = kr.close();
+    let signers = ps
+        .into_iter()
+        .map(|p| p.new_signer(closed_reg.clone()).unwrap())
+        .collect();
+    (signers, closed_reg)
+}
+
+/// Key material of one party per entry of `stake`, derived from `seed`, and
+/// the still open registration of their keys.
+// Keys freshly generated from the RNG are always valid and never registered
+// twice, so registration cannot fail here.
+#[allow(clippy::unwrap_used)]
+fn setup_initializers(
+    params: StmParameters,
+    stake: &[Stake],
+    seed: [u8; 32],
+) -> (Vec<StmInitializer>, KeyReg) {
+    // Each party draws from its own stream of the seeded RNG, so the keys do
+    // not depend on the order in which the parties are set up.
+    let setup = |(i, &stake): (usize, &Stake)| {
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        rng.set_stream(i as u64);
+        StmInitializer::setup(params, stake, &mut rng)
+    };
+    #[cfg(feature = "rayon")]
+    let ps: Vec<StmInitializer> = stake.par_iter().enumerate().map(setup).collect();
+    #[cfg(not(feature = "rayon"))]
+    let ps: Vec<StmInitializer> = stake.iter().enumerate().map(setup).collect();
+
+    // Registration is stateful, so it always happens in order.
+    let mut kr = KeyReg::init();
+    for (p, &stake) in ps.iter().zip(stake) {
+        kr.register(stake, p.verification_key()).unwrap();
+    }
+    (ps, kr)
+}
+
+/// Like [sign_message], but keep only the wins among the lottery `indices`,
+/// e.g. to see which indices a party wins while tuning `m` and `phi_f`.
+///
+/// mithril-stm always plays every lottery in `0..m`, so the signature is
+/// produced as usual and its other indices dropped; it still verifies, since
+/// each index is checked on its own. Returns `None` if the party wins none of
+/// `indices`.
+pub fn sign_with_indices(signer: &StmSigner<D>, msg: &[u8], indices: &[u64]) -> Option<StmSig> {
+    let mut sig = sign_message(signer, msg)?;
+    sig.indexes.retain(|index| indices.contains(index));
+    (!sig.indexes.is_empty()).then_some(sig)
+}
+
+/// Lottery indices won by the party that produced `sig`.
+pub fn winning_indices(sig: &StmSig) -> Vec<u64> {
+    sig.indexes.clone()
+}
+
+/// Verification key of `signer`, as registered for it, e.g. for a node to
+/// advertise to its peers while the committee is being formed.
+pub fn signer_verification_key(signer: &StmSigner<D>) -> StmVerificationKey {
+    signer.verification_key()
+}
+
+/// Run the lottery for the signers in `ps` selected by `is` and collect the
+/// signatures of the winners.
+///
+/// Indices outside of `ps` are skipped, just like signers that lose the
+/// lottery, so untrusted indices can never cause a panic.
+pub fn find_signatures(msg: &[u8], ps: &[StmSigner<D>], is: &[usize]) -> Vec<StmSig> {
+    find_signatures_with_indices(msg, ps, is)
+        .into_iter()
+        .map(|(_, sig)| sig)
+        .collect()
+}
+
+/// Like [find_signatures], but pair each signature with the index in `ps` of
+/// the party that produced it, e.g. for auditing which parties contributed.
+///
+/// With the `rayon` feature the parties sign in parallel; the signatures are
+/// still returned in the order of `is`.
+pub fn find_signatures_with_indices(
+    msg: &[u8],
+    ps: &[StmSigner<D>],
+    is: &[usize],
+) -> Vec<(usize, StmSig)> {
+    #[cfg(feature = "rayon")]
+    let is = is.par_iter();
+    #[cfg(not(feature = "rayon"))]
+    let is = is.iter();
+
+    is.filter_map(|&i| ps.get(i).map(|p| (i, p)))
+        .filter_map(|(i, p)| sign_message(p, msg).map(|sig| (i, sig)))
+        .collect()
+}
+
+/// Run the lottery over `msg` for `signer` alone and return its signature, or
+/// `None` if it wins no lottery index.
+///
+/// This lets each party sign on its own, e.g. in a separate process answering
+/// a [SigningRequest](crate::SigningRequest).
+pub fn sign_message(signer: &StmSigner<D>, msg: &[u8]) -> Option<StmSig> {
+    signer.sign(msg)
+}
+
+/// Produce an aggregate signature over `msg` from a committee of `nparties`
+/// equal-stake parties. Verification only accepts messages of 1 to
+/// [MAX_MESSAGE_LEN](crate::MAX_MESSAGE_LEN) bytes, so other messages are
+/// rejected with [AggregationError::EmptyMessage] or
+/// [AggregationError::MessageTooLong] before any party signs.
+///
+/// If the parties win fewer than `params.k` distinct lottery indices, e.g.
+/// because `phi_f` is too low for `k`, this fails with
+/// [AggregationError::NotEnoughSignatures] carrying the counts achieved, so the
+/// caller can retry with a higher `phi_f` or a lower `k`.
+///
+/// A single party is a valid committee. It holds all the stake, so it wins
+/// each of the `m` lotteries with probability `phi_f` and can reach a quorum
+/// of more than one index on its own; when it does not, the same error is
+/// returned rather than a panic.
+pub fn generate_aggregate_signatures(
+    msg: &[u8],
+    params: StmParameters,
+    nparties: usize,
+) -> Result<StmAggrSig<H>, AggregationError> {
+    generate_aggregate_signatures_for_stake(msg, params, vec![1; nparties])
+}
+
+/// Produce an aggregate signature over `msg` from the smallest committee of
+/// equal-stake parties, doubling from `start_parties` up to `max_parties`,
+/// that wins enough lotteries for a quorum, together with its size.
+///
+/// Growing the committee does not make a quorum more likely: each of the `m`
+/// lotteries is won by some party with probability `phi_f` whatever the number
+/// of equal-stake parties, so every attempt expects `phi_f * m` distinct
+/// winning indices and only draws new keys. More parties win more indices in
+/// total, towards `-ln(1 - phi_f) * m`, but the extra wins are duplicates,
+/// which do not count towards `k`. This therefore only helps when `phi_f * m`
+/// is close to `k`; if `max_parties` parties still fall short, it fails with
+/// [AggregationError::QuorumUnreachable].
+pub fn generate_until_quorum(
+    msg: &[u8],
+    params: StmParameters,
+    start_parties: usize,
+    max_parties: usize,
+) -> Result<(StmAggrSig<H>, usize), AggregationError> {
+    let mut nparties = start_parties.min(max_parties);
+    loop {
+        match generate_aggregate_signatures(msg, params, nparties) {
+            Ok(msig) => return Ok((msig, nparties)),
+            Err(AggregationError::NotEnoughSignatures { .. }) if nparties < max_parties => {
+                debug!(nparties, "no quorum, growing the committee");
+                nparties = nparties.saturating_mul(2).min(max_parties);
+            }
+            Err(AggregationError::NotEnoughSignatures { indices, k, .. }) => {
+                return Err(AggregationError::QuorumUnreachable {
+                    max_parties: nparties,
+                    indices,
+                    k,
+                })
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Produce an aggregate signature over `msg` from a committee holding one
+/// party per entry of `stake`, all of which attempt to sign.
+///
+/// Fails if `stake` is empty or adds up to more than a [Stake] can hold, or if
+/// too few signatures win the lottery to reach the quorum of `params.k`.
+#[instrument(
+    name = "aggregate_certificate",
+    skip_all,
+    fields(nparties = stake.len(), k = params.k, m = params.m, phi_f = params.phi_f)
+)]
+pub fn generate_aggregate_signatures_for_stake(
+    msg: &[u8],
+    params: StmParameters,
+    stake: Vec<Stake>,
+) -> Result<StmAggrSig<H>, AggregationError> {
+    // Closing an empty or overflowing registration panics in mithril-stm.
+    if stake.is_empty() {
+        return Err(AggregationError::NoParties);
+    }
+    checked_total_stake(&stake).map_err(|_| AggregationError::StakeOverflow)?;
+    let ps = setup_parties(params, stake);
+    let clerk = StmClerk::from_signer(&ps[0]);
+    aggregate(msg, &ps, &clerk)
+}
+
+/// Produce a certificate over `msg` from a committee holding one party per
+/// entry of `stake`, for a Mithril network that hashes its registration and
+/// certificates with `D` rather than the [DefaultHasher].
+///
+/// The certificate and the aggregate verification key of the committee are
+/// returned encoded as [serialize_certificate](crate::serialize_certificate)
+/// and [serialize_avk](crate::serialize_avk) encode them, which is what
+/// [verify_any](crate::verify_any) takes. Keys are derived from
+/// [DEFAULT_SEED], and failures are those of
+/// [generate_aggregate_signatures_for_stake].
+// Building a signer from its own initializer and the registration of its
+// key never fails. mithril-stm takes the registration by value, so each
+// signer gets its own copy of the parties; the Merkle tree is shared.
+#[allow(clippy::unwrap_used)]
+pub fn generate_certificate_with_digest<D>(
+    msg: &[u8],
+    params: StmParameters,
+    stake: Vec<Stake>,
+) -> Result<(Vec<u8>, Vec<u8>), AggregationError>
+where
+    D: Clone + Digest + FixedOutput,
+    StmAggrSig<D>: Serialize,
+    StmAggrVerificationKey<D>: Serialize,
+{
+    if stake.is_empty() {
+        return Err(AggregationError::NoParties);
+    }
+    checked_total_stake(&stake).map_err(|_| AggregationError::StakeOverflow)?;
+    check_signed_message(msg)?;
+    let (ps, kr) = setup_initializers(params, &stake, DEFAULT_SEED);
+    let reg = kr.close::<D>();
+    let ps: Vec<StmSigner<D>> = ps
+        .into_iter()
+        .map(|p| p.new_signer(reg.clone()).unwrap())
+        .collect();
+    let sigs = ps.iter().filter_map(|p| p.sign(msg)).collect();
+    let msig = aggregate_with(msg, sigs, &StmClerk::from_signer(&ps[0]))?;
+    let avk = StmAggrVerificationKey::from(&reg);
+    Ok((encode_versioned(&msig), encode(&avk)))
+}
+
+/// Produce one aggregate signature per message of `msgs`, each from its own
+/// lottery among all signers of `ps`.
+///
+/// The signers carry their parameters and closed registration, so the
+/// committee is set up once for the whole batch.
+pub fn generate_aggregate_signatures_for(
+    msgs: &[Vec<u8>],
+    ps: &[StmSigner<D>],
+) -> Vec<Result<StmAggrSig<H>, AggregationError>> {
+    let Some(first) = ps.first() else {
+        return msgs.iter().map(|_| Err(AggregationError::NoParties)).collect();
+    };
+    let clerk = StmClerk::from_signer(first);
+    msgs.iter().map(|msg| aggregate(msg, ps, &clerk)).collect()
+}
+
+/// Produce an aggregate signature over `msg` from only the signers of `ps`
+/// selected by `indices`, as when the other parties are offline.
+///
+/// The certificate still verifies against the aggregate verification key of
+/// the whole committee, provided the selected parties win enough lotteries for
+/// a quorum. Indices outside of `ps` are skipped as in [find_signatures].
+pub fn generate_aggregate_from_subset(
+    ps: &[StmSigner<D>],
+    indices: &[usize],
+    msg: &[u8],
+) -> Result<StmAggrSig<H>, AggregationError> {
+    let first = ps.first().ok_or(AggregationError::NoParties)?;
+    check_signed_message(msg)?;
+    let sigs = find_signatures(msg, ps, indices);
+    debug!(signatures = sigs.len(), "lottery finished");
+    aggregate_signatures(msg, sigs, &StmClerk::from_signer(first))
+}
+
+/// Bind `msg` to the protocol named by `domain`, as signed by
+/// [sign_with_domain].
+///
+/// The domain is length-prefixed, so no two `(domain, msg)` pairs give the same
+/// bytes.
+pub fn domain_separated(domain: &[u8], msg: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(8 + domain.len() + msg.len());
+    bytes.extend_from_slice(&(domain.len() as u64).to_be_bytes());
+    bytes.extend_from_slice(domain);
+    bytes.extend_from_slice(msg);
+    bytes
+}
+
+/// Produce an aggregate signature from all signers of `ps` over `msg` bound to
+/// `domain`, usually [DEFAULT_DOMAIN], so that it cannot be replayed in
+/// another protocol.
+///
+/// The certificate only verifies with
+/// [verify_with_domain](crate::verify_with_domain) under the same domain.
+pub fn sign_with_domain(
+    domain: &[u8],
+    msg: &[u8],
+    ps: &[StmSigner<D>],
+) -> Result<StmAggrSig<H>, AggregationError> {
+    let first = ps.first().ok_or(AggregationError::NoParties)?;
+    aggregate(
+        &domain_separated(domain, msg),
+        ps,
+        &StmClerk::from_signer(first),
+    )
+}
+
+/// Blake2b-256 digest of `msg`, which [sign_digest] signs in place of the
+/// message.
+pub fn message_digest(msg: &[u8]) -> [u8; 32] {
+    DefaultHasher::digest(msg).into()
+}
+
+/// Produce an aggregate signature from all signers of `ps` over the
+/// [message_digest] of `msg` rather than over `msg` itself.
+///
+/// The certificate then verifies with [verify_digest](crate::verify_digest)
+/// given only the 32-byte digest, so large messages need not be passed to the
+/// verifier.
+pub fn sign_digest(msg: &[u8], ps: &[StmSigner<D>]) -> Result<StmAggrSig<H>, AggregationError> {
+    let first = ps.first().ok_or(AggregationError::NoParties)?;
+    aggregate(&message_digest(msg), ps, &StmClerk::from_signer(first))
+}
+
+/// How a message is turned into the bytes the parties sign. Signers and
+/// verifiers of a committee must agree on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageMode {
+    /// Sign the message itself, which avoids the indirection of a digest for
+    /// small payloads.
+    Raw,
+    /// Sign the [message_digest] of the message, as [sign_digest] does.
+    Digest,
+}
+
+impl MessageMode {
+    /// The bytes the parties sign for `msg` in this mode.
+    pub fn signed_bytes(self, msg: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Raw => msg.to_vec(),
+            Self::Digest => message_digest(msg).to_vec(),
+        }
+    }
+}
+
+/// Produce an aggregate signature from all signers of `ps` over `msg` signed
+/// in `mode`.
+///
+/// The certificate only verifies with
+/// [verify_with_mode](crate::verify_with_mode) in the same mode.
+pub fn sign_with_mode(
+    mode: MessageMode,
+    msg: &[u8],
+    ps: &[StmSigner<D>],
+) -> Result<StmAggrSig<H>, AggregationError> {
+    let first = ps.first().ok_or(AggregationError::NoParties)?;
+    aggregate(&mode.signed_bytes(msg), ps, &StmClerk::from_signer(first))
+}
+
+/// Run the lottery over `msg` for all signers of `ps` and aggregate the
+/// winning signatures with `clerk`.
+fn aggregate(
+    msg: &[u8],
+    ps: &[StmSigner<D>],
+    clerk: &StmClerk<D>,
+) -> Result<StmAggrSig<H>, AggregationError> {
+    check_signed_message(msg)?;
+    let all_ps: Vec<usize> = (0..ps.len()).collect();
+    let sigs = find_signatures(msg, ps, &all_ps);
+    debug!(signatures = sigs.len(), "lottery finished");
+    aggregate_signatures(msg, sigs, clerk)
+}
+
+/// Aggregate `sigs` over `msg` with `clerk`, e.g. after merging signatures
+/// collected from several sources.
+///
+/// Signatures are first passed through [dedup_signatures], so a party that
+/// appears more than once is only counted once, then sorted by signer index,
+/// so the serialized certificate is the same whatever order the signatures
+/// were collected in. Fails with [AggregationError::NoSignatures] if no
+/// signature is left, e.g. because every party lost the lottery.
+pub fn aggregate_signatures(
+    msg: &[u8],
+    sigs: Vec<StmSig>,
+    clerk: &StmClerk<D>,
+) -> Result<StmAggrSig<H>, AggregationError> {
+    aggregate_with(msg, sigs, clerk)
+}
+
+/// [aggregate_signatures] for a committee hashed with any digest.
+fn aggregate_with<D: Clone + Digest + FixedOutput>(
+    msg: &[u8],
+    sigs: Vec<StmSig>,
+    clerk: &StmClerk<D>,
+) -> Result<StmAggrSig<D>, AggregationError> {
+    check_signed_message(msg)?;
+    let mut sigs = dedup_signatures(sigs);
+    sigs.sort_by_key(|sig| sig.signer_index);
+    if sigs.is_empty() {
+        return Err(AggregationError::NoSignatures);
+    }
+    clerk.aggregate(&sigs, msg).map_err(|err| match err {
+        mithril_stm::AggregationError::NotEnoughSignatures(indices, k) => {
+            AggregationError::NotEnoughSignatures {
+                signatures: sigs.len(),
+                indices,
+                k,
+            }
+        }
+        mithril_stm::AggregationError::UsizeConversionInvalid => {
+            AggregationError::UsizeConversionInvalid
+        }
+    })
+}
+
+/// Aggregate `sigs` over `msg` for the committee of `reg` under `params`, as
+/// when signatures are collected from nodes that each hold different signers
+/// of the same registration.
+///
+/// The clerk is built from `reg` itself, so no signer needs to be held
+/// locally. Otherwise behaves like [aggregate_signatures]; fails with
+/// [AggregationError::NoParties] if `reg` is empty.
+pub fn aggregate_external(
+    reg: &ClosedKeyReg<D>,
+    sigs: &[StmSig],
+    msg: &[u8],
+    params: StmParameters,
+) -> Result<StmAggrSig<H>, AggregationError> {
+    if reg.reg_parties.is_empty() {
+        return Err(AggregationError::NoParties);
+    }
+    let clerk = StmClerk::from_registration(&params, reg);
+    aggregate_signatures(msg, sigs.to_vec(), &clerk)
+}
+
+/// Combine two aggregate signatures over `msg` from the same committee into
+/// one, by re-aggregating the union of their signatures with `clerk`.
+///
+/// mithril-stm cannot merge aggregates directly, and only builds aggregates
+/// that reach the quorum, so each part must already reach it; subcommittees
+/// that fall short should forward their raw signatures to
+/// [aggregate_signatures] instead. Signers present in both parts are counted
+/// once, and as for any aggregate mithril-stm keeps only as many signatures as
+/// the quorum needs.
+pub fn merge_aggregates(
+    a: &StmAggrSig<H>,
+    b: &StmAggrSig<H>,
+    msg: &[u8],
+    clerk: &StmClerk<D>,
+) -> Result<StmAggrSig<H>, AggregationError> {
+    let sigs = signatures(a)
+        .into_iter()
+        .chain(signatures(b))
+        .map(|sig_reg| sig_reg.sig)
+        .collect();
+    aggregate_signatures(msg, sigs, clerk)
+}
+
+/// Reject a message that verification would reject, before any party signs
+/// it.
+fn check_signed_message(msg: &[u8]) -> Result<(), AggregationError> {
+    // check_message only reports these two errors.
+    match check_message(msg) {
+        Ok(()) => Ok(()),
+        Err(VerificationError::MessageTooLong { len, max }) => {
+            Err(AggregationError::MessageTooLong { len, max })
+        }
+        Err(_) => Err(AggregationError::EmptyMessage),
+    }
+}
+
+/// Keep only the first signature of each signer, identified by its
+/// `signer_index` in the closed registration, preserving the order of `sigs`.
+pub fn dedup_signatures(sigs: Vec<StmSig>) -> Vec<StmSig> {
+    let mut seen = BTreeSet::new();
+    let total = sigs.len();
+    let sigs: Vec<StmSig> = sigs
+        .into_iter()
+        .filter(|sig| seen.insert(sig.signer_index))
+        .collect();
+    if sigs.len() < total {
+        debug!(dropped = total - sigs.len(), "dropped duplicate signatures");
+    }
+    sigs
+}
+
+/// Derive the aggregate verification key of the committee built by
+/// [setup_parties] for `stake`.
+///
+/// Panics if `stake` is empty or adds up to more than a [Stake] can hold; see
+/// [try_compute_avk_for_stake].
+pub fn compute_avk_for_stake(
+    stake: Vec<Stake>,
+    params: StmParameters,
+) -> StmAggrVerificationKey<D> {
+    try_compute_avk_for_stake(stake, params)
+        .unwrap_or_else(|err| panic!("invalid stake distribution: {err:?}"))
+}
+
+/// Like [compute_avk_for_stake], but report a `stake` that is empty or
+/// overflows as an error instead of panicking, e.g. in the zkVM guest where a
+/// panic aborts the whole proof.
+pub fn try_compute_avk_for_stake(
+    stake: Vec<Stake>,
+    params: StmParameters,
+) -> Result<StmAggrVerificationKey<D>, RegistrationError> {
+    // Closing such a registration panics in mithril-stm.
+    if stake.is_empty() {
+        return Err(RegistrationError::Empty);
+    }
+    checked_total_stake(&stake)?;
+
+    let (_, closed_reg) = setup_committee(params, stake);
+    try_compute_avk_for_reg(&closed_reg)
+}
+
+/// Derive the aggregate verification key of the registration of `clerk`,
+/// failing instead of panicking if the registration holds no party.
+pub fn try_compute_avk(
+    clerk: &StmClerk<D>,
+) -> Result<StmAggrVerificationKey<D>, RegistrationError> {
+    // mithril-stm indexes into the Merkle tree of an empty registration.
+    if clerk.get_reg_party(&0).is_none() {
+        return Err(RegistrationError::Empty);
+    }
+    Ok(clerk.compute_avk())
+}
+
+/// Derive the aggregate verification key of `closed_reg`, e.g. as returned by
+/// [setup_committee], without building a clerk, failing instead of panicking
+/// if the registration holds no party.
+pub fn try_compute_avk_for_reg(
+    closed_reg: &ClosedKeyReg<D>,
+) -> Result<StmAggrVerificationKey<D>, RegistrationError> {
+    // mithril-stm indexes into the Merkle tree of an empty registration.
+    if closed_reg.reg_parties.is_empty() {
+        return Err(RegistrationError::Empty);
+    }
+    Ok(StmAggrVerificationKey::from(closed_reg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        default_params, serialize_avk, serialize_certificate, verify_aggregate_signature,
+        verify_with_mode, MAX_MESSAGE_LEN,
+    };
+
+    const MSG: &[u8] = b"signing tests";
+
+    #[test]
+    fn committees_of_any_size_round_trip_through_verification() {
+        let params = default_params();
+        for nparties in [1, 4, 50] {
+            let msig = generate_aggregate_signatures(MSG, params, nparties).unwrap();
+            let avk = compute_avk_for_stake(vec![1; nparties], params);
+            verify_aggregate_signature(MSG, &msig, &avk, params).unwrap();
+        }
+    }
+
+    #[test]
+    fn out_of_range_signer_indices_are_skipped() {
+        let ps = setup_equal_parties(default_params(), 4);
+        let signers: Vec<usize> = find_signatures_with_indices(MSG, &ps, &[0, 1, 99])
+            .into_iter()
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(signers, [0, 1]);
+        assert_eq!(find_signatures(MSG, &ps, &[99]).len(), 0);
+    }
+
+    #[test]
+    fn distinct_seeds_yield_distinct_keys() {
+        let params = default_params();
+        let default = setup_equal_parties(params, 2);
+        let again = setup_equal_parties_with_seed(params, 2, DEFAULT_SEED);
+        let other = setup_equal_parties_with_seed(params, 2, [1; 32]);
+
+        for ((default, again), other) in default.iter().zip(&again).zip(&other) {
+            let vk = signer_verification_key(default);
+            assert!(vk == signer_verification_key(again));
+            assert!(vk != signer_verification_key(other));
+        }
+    }
+
+    #[test]
+    fn too_few_lottery_wins_are_reported() {
+        let params = StmParameters {
+            k: 2642,
+            m: 2642,
+            phi_f: 0.2,
+        };
+        match generate_aggregate_signatures(MSG, params, 4) {
+            Err(err @ AggregationError::NotEnoughSignatures { indices, k, .. }) => {
+                assert_eq!(k, 2642);
+                assert!(indices < k);
+                assert!(err.to_string().contains("fewer than k = 2642"));
+            }
+            Err(err) => panic!("expected too few signatures, got {err:?}"),
+            Ok(_) => panic!("expected too few signatures, got a certificate"),
+        }
+    }
+
+    #[test]
+    fn winning_parties_travel_with_their_signatures() {
+        let all = [0, 1, 2, 3];
+        let certain = StmParameters {
+            k: 1,
+            m: 4,
+            phi_f: 1.0,
+        };
+        let ps = setup_equal_parties(certain, 4);
+        let winners: Vec<usize> = find_signatures_with_indices(MSG, &ps, &all)
+            .into_iter()
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(winners, all);
+
+        let unlikely = StmParameters {
+            k: 1,
+            m: 4,
+            phi_f: 0.05,
+        };
+        let ps = setup_equal_parties(unlikely, 4);
+        let sigs = find_signatures_with_indices(MSG, &ps, &all);
+        assert!(sigs.len() < all.len());
+        for (i, sig) in sigs {
+            let own = sign_message(&ps[i], MSG).unwrap();
+            assert_eq!(winning_indices(&sig), winning_indices(&own));
+        }
+    }
+
+    #[test]
+    fn setup_matches_a_sequential_registration() {
+        let params = default_params();
+        let stake = vec![5, 1, 3, 2];
+        let seed = [9; 32];
+        let (_, reg) = setup_committee_with_seed(params, stake.clone(), seed);
+
+        let mut kr = KeyReg::init();
+        for (i, &stake) in stake.iter().enumerate() {
+            let mut rng = ChaCha20Rng::from_seed(seed);
+            rng.set_stream(i as u64);
+            let initializer = StmInitializer::setup(params, stake, &mut rng);
+            kr.register(stake, initializer.verification_key()).unwrap();
+        }
+        let sequential = kr.close();
+
+        assert_eq!(
+            serialize_avk(&StmAggrVerificationKey::from(&reg)),
+            serialize_avk(&StmAggrVerificationKey::from(&sequential))
+        );
+    }
+
+    #[test]
+    fn batch_certificates_only_verify_their_own_message() {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let avk = StmAggrVerificationKey::from(&reg);
+        let msgs = vec![
+            b"epoch 1".to_vec(),
+            b"epoch 2".to_vec(),
+            b"epoch 3".to_vec(),
+        ];
+
+        let msigs = generate_aggregate_signatures_for(&msgs, &ps);
+        assert_eq!(msigs.len(), msgs.len());
+        for (i, msig) in msigs.iter().enumerate() {
+            let msig = msig.as_ref().unwrap();
+            for (j, msg) in msgs.iter().enumerate() {
+                let result = verify_aggregate_signature(msg, msig, &avk, params);
+                assert_eq!(result.is_ok(), i == j, "certificate {i}, message {j}");
+            }
+        }
+    }
+
+    #[test]
+    fn duplicated_signatures_are_aggregated_once() {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let mut sigs = find_signatures(MSG, &ps, &[0, 1, 2, 3]);
+        let distinct = sigs.len();
+        sigs.push(sigs[0].clone());
+        sigs.insert(1, sigs[0].clone());
+
+        assert_eq!(dedup_signatures(sigs.clone()).len(), distinct);
+        let msig = aggregate_signatures(MSG, sigs, &StmClerk::from_signer(&ps[0])).unwrap();
+        let avk = StmAggrVerificationKey::from(&reg);
+        verify_aggregate_signature(MSG, &msig, &avk, params).unwrap();
+    }
+
+    #[test]
+    fn under_provisioned_committee_reports_its_counts() {
+        let params = StmParameters {
+            phi_f: 0.1,
+            ..default_params()
+        };
+        match generate_aggregate_signatures(MSG, params, 2) {
+            Err(AggregationError::NotEnoughSignatures {
+                signatures,
+                indices,
+                k,
+            }) => {
+                assert!((1..=2).contains(&signatures));
+                assert!(indices > 0 && indices < k);
+                assert_eq!(k, params.k);
+            }
+            Err(err) => panic!("expected too few signatures, got {err:?}"),
+            Ok(_) => panic!("expected too few signatures, got a certificate"),
+        }
+    }
+
+    #[test]
+    fn absent_signers_do_not_invalidate_a_quorum() {
+        let params = StmParameters {
+            k: 100,
+            ..default_params()
+        };
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let avk = StmAggrVerificationKey::from(&reg);
+
+        let msig = generate_aggregate_from_subset(&ps, &[0, 2], MSG).unwrap();
+        verify_aggregate_signature(MSG, &msig, &avk, params).unwrap();
+
+        // Aggregation keeps only the signatures needed to reach `k`, which
+        // may be those of a single party.
+        let signers: Vec<u64> = signatures(&msig)
+            .iter()
+            .map(|s| s.sig.signer_index)
+            .collect();
+        assert!(!signers.is_empty());
+        assert!(signers.iter().all(|i| [0, 2].contains(i)), "{signers:?}");
+    }
+
+    #[test]
+    fn subset_of_lottery_losers_has_no_signatures() {
+        let params = StmParameters {
+            k: 1,
+            m: 64,
+            phi_f: 0.2,
+        };
+        // Party 0 holds a negligible share of the stake, so it loses every
+        // lottery, while party 1 wins about a fifth of them.
+        let (ps, reg) = setup_committee(params, vec![1, 1_000_000_000]);
+
+        assert_eq!(
+            generate_aggregate_from_subset(&ps, &[0], MSG).err(),
+            Some(AggregationError::NoSignatures)
+        );
+        let msig = generate_aggregate_from_subset(&ps, &[0, 1], MSG).unwrap();
+        let avk = StmAggrVerificationKey::from(&reg);
+        verify_aggregate_signature(MSG, &msig, &avk, params).unwrap();
+    }
+
+    #[test]
+    fn lottery_without_winners_has_no_signatures() {
+        let ps = setup_equal_parties(default_params(), 4);
+        assert_eq!(
+            generate_aggregate_from_subset(&ps, &[], MSG).err(),
+            Some(AggregationError::NoSignatures)
+        );
+        assert_eq!(
+            aggregate_signatures(MSG, vec![], &StmClerk::from_signer(&ps[0])).err(),
+            Some(AggregationError::NoSignatures)
+        );
+        assert!(AggregationError::NoSignatures
+            .to_string()
+            .contains("no party won"));
+    }
+
+    #[test]
+    fn growing_the_committee_cannot_reach_an_unlikely_quorum() {
+        // About phi_f * m = 528 distinct indices are expected whatever the
+        // committee size, well short of k.
+        let params = StmParameters {
+            k: 1000,
+            ..default_params()
+        };
+        match generate_until_quorum(MSG, params, 1, 8) {
+            Err(AggregationError::QuorumUnreachable {
+                max_parties,
+                indices,
+                k,
+            }) => {
+                assert_eq!(max_parties, 8);
+                assert!(indices < k);
+                assert_eq!(k, 1000);
+            }
+            Err(err) => panic!("expected an unreachable quorum, got {err:?}"),
+            Ok(_) => panic!("expected an unreachable quorum, got a certificate"),
+        }
+
+        let params = default_params();
+        let (msig, nparties) = generate_until_quorum(MSG, params, 1, 8).unwrap();
+        assert_eq!(nparties, 1);
+        let avk = compute_avk_for_stake(vec![1; nparties], params);
+        verify_aggregate_signature(MSG, &msig, &avk, params).unwrap();
+    }
+
+    #[test]
+    fn broken_registrations_are_errors_rather_than_panics() {
+        let params = default_params();
+        let (_, mut reg) = setup_committee(params, vec![1; 4]);
+        reg.reg_parties.clear();
+
+        assert_eq!(
+            try_compute_avk_for_reg(&reg).err(),
+            Some(RegistrationError::Empty)
+        );
+        let clerk = StmClerk::from_registration(&params, &reg);
+        assert_eq!(
+            try_compute_avk(&clerk).err(),
+            Some(RegistrationError::Empty)
+        );
+        assert_eq!(
+            try_compute_avk_for_stake(vec![], params).err(),
+            Some(RegistrationError::Empty)
+        );
+        assert_eq!(
+            try_compute_avk_for_stake(vec![Stake::MAX, 1], params).err(),
+            Some(RegistrationError::StakeOverflow)
+        );
+    }
+
+    #[test]
+    fn subcommittee_aggregates_merge_into_one_certificate() {
+        // Two parties win about 280 distinct indices, one only about 140.
+        let params = StmParameters {
+            k: 200,
+            ..default_params()
+        };
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let avk = StmAggrVerificationKey::from(&reg);
+        let clerk = StmClerk::from_signer(&ps[0]);
+        let a = generate_aggregate_from_subset(&ps, &[0, 1], MSG).unwrap();
+        let b = generate_aggregate_from_subset(&ps, &[2, 3], MSG).unwrap();
+
+        let merged = merge_aggregates(&a, &b, MSG, &clerk).unwrap();
+        verify_aggregate_signature(MSG, &merged, &avk, params).unwrap();
+        let signers: BTreeSet<u64> = signatures(&merged)
+            .iter()
+            .map(|s| s.sig.signer_index)
+            .collect();
+        assert!(signers.len() >= 2);
+        assert!(signers.iter().all(|&i| i < 4));
+
+        let same = merge_aggregates(&a, &a, MSG, &clerk).unwrap();
+        assert_eq!(signatures(&same).len(), signatures(&a).len());
+    }
+
+    #[test]
+    fn returned_registration_gives_the_committee_key() {
+        let params = default_params();
+        let stake = vec![3, 1, 2, 5];
+        let (ps, reg) = setup_committee(params, stake.clone());
+        let avk = try_compute_avk_for_reg(&reg).unwrap();
+
+        assert_eq!(
+            serialize_avk(&avk),
+            serialize_avk(&compute_avk_for_stake(stake, params))
+        );
+        let msig = sign_with_mode(MessageMode::Raw, MSG, &ps).unwrap();
+        verify_aggregate_signature(MSG, &msig, &avk, params).unwrap();
+    }
+
+    #[test]
+    fn messages_that_cannot_verify_are_not_signed() {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let too_long = vec![0; MAX_MESSAGE_LEN + 1];
+        let too_long_err = AggregationError::MessageTooLong {
+            len: MAX_MESSAGE_LEN + 1,
+            max: MAX_MESSAGE_LEN,
+        };
+
+        assert_eq!(
+            generate_aggregate_signatures(b"", params, 4).err(),
+            Some(AggregationError::EmptyMessage)
+        );
+        assert_eq!(
+            generate_aggregate_from_subset(&ps, &[0, 1, 2, 3], &too_long).err(),
+            Some(too_long_err.clone())
+        );
+        assert_eq!(
+            generate_aggregate_signatures_for(&[vec![], too_long.clone()], &ps)
+                .into_iter()
+                .map(Result::err)
+                .collect::<Vec<_>>(),
+            [
+                Some(AggregationError::EmptyMessage),
+                Some(too_long_err.clone())
+            ]
+        );
+
+        let sigs = find_signatures(MSG, &ps, &[0, 1, 2, 3]);
+        let clerk = StmClerk::from_signer(&ps[0]);
+        assert_eq!(
+            aggregate_signatures(b"", sigs.clone(), &clerk).err(),
+            Some(AggregationError::EmptyMessage)
+        );
+        assert_eq!(
+            aggregate_external(&reg, &sigs, &too_long, params).err(),
+            Some(too_long_err)
+        );
+    }
+
+    #[test]
+    fn signatures_aggregate_from_the_registration_alone() {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        // Two nodes each hold half of the signers.
+        let mut sigs = find_signatures(MSG, &ps[..2], &[0, 1]);
+        sigs.extend(find_signatures(MSG, &ps[2..], &[0, 1]));
+        drop(ps);
+
+        let msig = aggregate_external(&reg, &sigs, MSG, params).unwrap();
+        let avk = StmAggrVerificationKey::from(&reg);
+        verify_aggregate_signature(MSG, &msig, &avk, params).unwrap();
+
+        let (_, mut empty) = setup_committee(params, vec![1]);
+        empty.reg_parties.clear();
+        assert_eq!(
+            aggregate_external(&empty, &sigs, MSG, params).err(),
+            Some(AggregationError::NoParties)
+        );
+    }
+
+    #[test]
+    fn aggregation_order_does_not_change_the_certificate_bytes() {
+        let (ps, _) = setup_committee(default_params(), vec![1; 4]);
+        let sigs = find_signatures(MSG, &ps, &[0, 1, 2, 3]);
+        assert!(sigs.len() > 1);
+        let mut reversed = sigs.clone();
+        reversed.reverse();
+
+        let forward = aggregate_signatures(MSG, sigs, &StmClerk::from_signer(&ps[0])).unwrap();
+        let backward = aggregate_signatures(MSG, reversed, &StmClerk::from_signer(&ps[3])).unwrap();
+        assert_eq!(
+            serialize_certificate(&forward),
+            serialize_certificate(&backward)
+        );
+    }
+
+    #[test]
+    fn messages_holding_every_byte_value_sign_and_verify() {
+        let params = default_params();
+        let msg: Vec<u8> = (0..=u8::MAX).collect();
+        assert!(String::from_utf8(msg.clone()).is_err());
+
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let avk = StmAggrVerificationKey::from(&reg);
+        for mode in [MessageMode::Raw, MessageMode::Digest] {
+            let msig = sign_with_mode(mode, &msg, &ps).unwrap();
+            verify_with_mode(mode, &msg, &msig, &avk, params).unwrap();
+        }
+        let msig = generate_aggregate_signatures(&msg, params, 4).unwrap();
+        let avk = compute_avk_for_stake(vec![1; 4], params);
+        verify_aggregate_signature(&msg, &msig, &avk, params).unwrap();
+    }
+
+    #[test]
+    fn signers_advertise_the_key_they_were_registered_with() {
+        let (initializers, kr) = setup_initializers(default_params(), &[3, 1, 2], DEFAULT_SEED);
+        let keys: Vec<StmVerificationKey> = initializers
+            .iter()
+            .map(|initializer| initializer.verification_key().vk)
+            .collect();
+        let reg = kr.close();
+
+        for ((initializer, vk), stake) in initializers.into_iter().zip(keys).zip([3, 1, 2]) {
+            let signer = initializer.new_signer(reg.clone()).unwrap();
+            assert!(signer_verification_key(&signer) == vk);
+            assert!(reg
+                .reg_parties
+                .iter()
+                .any(|party| party.0 == vk && party.1 == stake));
+        }
+    }
+
+    #[test]
+    fn single_party_reaches_the_quorum_only_with_enough_wins() {
+        let verify = |params: StmParameters| {
+            let msig = generate_aggregate_signatures(MSG, params, 1)?;
+            let avk = compute_avk_for_stake(vec![1], params);
+            verify_aggregate_signature(MSG, &msig, &avk, params).unwrap();
+            Ok::<_, AggregationError>(())
+        };
+        let params = |k, phi_f| StmParameters {
+            k,
+            phi_f,
+            ..default_params()
+        };
+
+        assert_eq!(verify(params(1, 0.2)), Ok(()));
+        assert_eq!(verify(params(357, 0.9)), Ok(()));
+        // About 26 expected wins, far from the quorum of 357 but never none.
+        assert!(matches!(
+            verify(params(357, 0.01)),
+            Err(AggregationError::NotEnoughSignatures {
+                signatures: 1,
+                k: 357,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn restricting_the_lottery_indices_leaves_fewer_signatures() {
+        let params = default_params();
+        // Each of 16 parties wins about 37 of the 2642 indices, so few win any
+        // of the first 10.
+        let ps = setup_equal_parties(params, 16);
+        let all: Vec<u64> = (0..params.m).collect();
+        let first: Vec<u64> = (0..10).collect();
+
+        let full: Vec<StmSig> = ps
+            .iter()
+            .filter_map(|p| sign_with_indices(p, MSG, &all))
+            .collect();
+        let restricted: Vec<StmSig> = ps
+            .iter()
+            .filter_map(|p| sign_with_indices(p, MSG, &first))
+            .collect();
+        assert_eq!(full.len(), 16);
+        assert!(restricted.len() < full.len());
+
+        for sig in &restricted {
+            let own = full
+                .iter()
+                .find(|own| own.signer_index == sig.signer_index)
+                .unwrap();
+            let won = winning_indices(sig);
+            assert!(!won.is_empty());
+            assert!(won
+                .iter()
+                .all(|index| *index < 10 && own.indexes.contains(index)));
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Loading of stake distributions from disk.
+
+use std::{fs, path::Path};
+
+use crate::{error::StakeFileError, sum_stake, Stake};
+
+/// Load the stake of each party from `path`, in file order.
+///
+/// Files ending in `.json` must hold a JSON array of stakes, e.g. `[10, 1, 1]`.
+/// Any other file is read as CSV with one `party_id,stake` record per line; a
+/// `party_id,stake` header and blank lines are skipped, and the party ids are
+/// only informative.
+///
+/// Every stake must be positive, the distribution must not be empty and the
+/// stakes must add up to at most [Stake::MAX].
+pub fn load_stake_distribution(path: &Path) -> Result<Vec<Stake>, StakeFileError> {
+    let contents = fs::read_to_string(path).map_err(|err| StakeFileError::Io(err.kind()))?;
+
+    let stake = if path.extension().map_or(false, |ext| ext == "json") {
+        parse_json(&contents)?
+    } else {
+        parse_csv(&contents)?
+    };
+
+    if stake.is_empty() {
+        return Err(StakeFileError::Empty);
+    }
+    if let Some(party) = stake.iter().position(|&s| s == 0) {
+        return Err(StakeFileError::ZeroStake { party });
+    }
+    let total = sum_stake(&stake);
+    if total > u128::from(Stake::MAX) {
+        return Err(StakeFileError::StakeOverflow { total });
+    }
+    Ok(stake)
+}
+
+fn parse_json(contents: &str) -> Result<Vec<Stake>, StakeFileError> {
+    serde_json::from_str(contents).map_err(|err| StakeFileError::InvalidJson {
+        reason: err.to_string(),
+    })
+}
+
+fn parse_csv(contents: &str) -> Result<Vec<Stake>, StakeFileError> {
+    let mut stake = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (i == 0 && line == "party_id,stake") {
+            continue;
+        }
+        let record = line
+            .split_once(',')
+            .and_then(|(_, s)| s.trim().parse().ok())
+            .ok_or(StakeFileError::InvalidRecord { line: i + 1 })?;
+        stake.push(record);
+    }
+    Ok(stake)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compute_avk_for_stake, default_params, generate_aggregate_signatures_for_stake,
+        verify_aggregate_signature, AggregationError,
+    };
+
+    #[test]
+    fn skewed_fixture_signs_and_verifies() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/skewed_stake.csv");
+        let stake = load_stake_distribution(&path).unwrap();
+        assert_eq!(stake, [1000, 200, 50, 10, 5, 1, 1, 1]);
+
+        let params = default_params();
+        let msg = b"weighted committee";
+        let msig = generate_aggregate_signatures_for_stake(msg, params, stake.clone()).unwrap();
+        let avk = compute_avk_for_stake(stake, params);
+        verify_aggregate_signature(msg, &msig, &avk, params).unwrap();
+    }
+
+    #[test]
+    fn csv_and_json_records_are_parsed() {
+        assert_eq!(parse_csv("party_id,stake\n0,3\n\n1, 4\n").unwrap(), [3, 4]);
+        assert_eq!(parse_json("[3, 4]").unwrap(), [3, 4]);
+        assert_eq!(
+            parse_csv("0,3\n1,-4\n"),
+            Err(StakeFileError::InvalidRecord { line: 2 })
+        );
+    }
+
+    #[test]
+    fn overflowing_distribution_is_rejected_with_its_total() {
+        let path = std::env::temp_dir().join(format!("bonsai-stake-{}.json", std::process::id()));
+        fs::write(&path, format!("[{}, {}, 2]", Stake::MAX, Stake::MAX)).unwrap();
+        let loaded = load_stake_distribution(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            loaded,
+            Err(StakeFileError::StakeOverflow {
+                total: 2 * u128::from(Stake::MAX) + 2
+            })
+        );
+        assert_eq!(
+            generate_aggregate_signatures_for_stake(
+                b"overflow",
+                default_params(),
+                vec![Stake::MAX, 1]
+            )
+            .err(),
+            Some(AggregationError::StakeOverflow)
+        );
+    }
+}
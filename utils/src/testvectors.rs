@@ -0,0 +1,145 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Known-good certificates pinned to disk, to catch dependency upgrades that
+//! change how certificates are produced or encoded.
+//!
+//! A vector is produced once with [generate_test_vector] and
+//! [write_test_vector]; [verify_test_vector] then fails as soon as the stored
+//! certificate no longer decodes or verifies against the stored key.
+
+use std::{fs, path::Path};
+
+use mithril_stm::stm::StmParameters;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compute_avk_for_stake, generate_aggregate_signatures_for_stake, serialize_avk,
+    serialize_certificate, verify_with_avk_bytes, AggregationError, Stake, TestVectorError,
+};
+
+/// A certificate together with everything needed to check it, as stored in a
+/// test vector file.
+///
+/// Byte fields are written as `0x`-prefixed hex strings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestVector {
+    /// The parameters the certificate was produced under.
+    pub params: StmParameters,
+    /// The stake of each party of the committee.
+    pub stake: Vec<Stake>,
+    /// The signed message.
+    #[serde(with = "hex_bytes")]
+    pub msg: Vec<u8>,
+    /// The [serialize_avk] encoding of the committee's key.
+    #[serde(with = "hex_bytes")]
+    pub avk: Vec<u8>,
+    /// The [serialize_certificate] encoding of the certificate.
+    #[serde(with = "hex_bytes")]
+    pub certificate: Vec<u8>,
+}
+
+/// Produce a certificate over `msg` from the committee holding `stake`, with
+/// key material derived from [DEFAULT_SEED](crate::DEFAULT_SEED).
+pub fn generate_test_vector(
+    params: StmParameters,
+    stake: Vec<Stake>,
+    msg: &[u8],
+) -> Result<TestVector, AggregationError> {
+    let msig = generate_aggregate_signatures_for_stake(msg, params, stake.clone())?;
+    let avk = compute_avk_for_stake(stake.clone(), params);
+    Ok(TestVector {
+        params,
+        stake,
+        msg: msg.to_vec(),
+        avk: serialize_avk(&avk),
+        certificate: serialize_certificate(&msig),
+    })
+}
+
+/// Write `vector` to `path` as pretty-printed JSON.
+pub fn write_test_vector(vector: &TestVector, path: &Path) -> Result<(), TestVectorError> {
+    let json =
+        serde_json::to_string_pretty(vector).map_err(|err| TestVectorError::InvalidJson {
+            reason: err.to_string(),
+        })?;
+    fs::write(path, json + "\n").map_err(|err| TestVectorError::Io(err.kind()))
+}
+
+/// Load the vector at `path` and check that its certificate still verifies.
+pub fn verify_test_vector(path: &Path) -> Result<(), TestVectorError> {
+    let json = fs::read_to_string(path).map_err(|err| TestVectorError::Io(err.kind()))?;
+    let vector: TestVector =
+        serde_json::from_str(&json).map_err(|err| TestVectorError::InvalidJson {
+            reason: err.to_string(),
+        })?;
+    verify_with_avk_bytes(&vector.msg, &vector.certificate, &vector.avk, vector.params)
+        .map_err(TestVectorError::Verification)
+}
+
+/// Serde representation of bytes as a `0x`-prefixed hex string.
+pub(crate) mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(value)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        hex::decode(s.strip_prefix("0x").unwrap_or(&s)).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// The vector committed to the repository.
+    fn fixture() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/test_vector.json")
+    }
+
+    #[test]
+    fn committed_vector_verifies() {
+        verify_test_vector(&fixture()).unwrap();
+    }
+
+    #[test]
+    fn committed_vector_is_reproduced() {
+        let json = fs::read_to_string(fixture()).unwrap();
+        let stored: TestVector = serde_json::from_str(&json).unwrap();
+        let regenerated =
+            generate_test_vector(stored.params, stored.stake.clone(), &stored.msg).unwrap();
+        assert_eq!(regenerated, stored);
+    }
+
+    #[test]
+    fn tampered_vector_fails() {
+        let json = fs::read_to_string(fixture()).unwrap();
+        let mut vector: TestVector = serde_json::from_str(&json).unwrap();
+        vector.msg.push(b'!');
+
+        let path = std::env::temp_dir().join(format!(
+            "bonsai-tampered-vector-{}.json",
+            std::process::id()
+        ));
+        write_test_vector(&vector, &path).unwrap();
+        let result = verify_test_vector(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(TestVectorError::Verification(_))));
+    }
+}
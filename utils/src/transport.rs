@@ -0,0 +1,412 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transport of certificates as `bytes32[]` contract calldata.
+//!
+//! A serialized certificate is several kilobytes long, far more than fits in a
+//! single word, so it is split into 32-byte words on the way in and
+//! reassembled before decoding. Each word carries up to 32 bytes of the
+//! certificate, for chains that prefer a smaller payload per word.
+
+use std::collections::BTreeMap;
+
+use mithril_stm::stm::StmAggrSig;
+
+use crate::{deserialize_certificate, CertificateError, H};
+
+/// Size in bytes of a `bytes32` calldata word.
+pub const WORD_SIZE: usize = 32;
+
+/// Size in bytes of the largest certificate that is reassembled, far above the
+/// size of a certificate under the [default_params](crate::default_params).
+pub const MAX_CERTIFICATE_LEN: usize = 1 << 20;
+
+/// A certificate split into zero-padded [WORD_SIZE] words, each carrying
+/// `chunk_size` bytes of it at its start, together with the metadata needed to
+/// put it back together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkedCertificate {
+    total_len: usize,
+    chunk_size: usize,
+    chunks: Vec<[u8; WORD_SIZE]>,
+}
+
+impl ChunkedCertificate {
+    /// Split `bytes` into full words, the layout contracts store.
+    pub fn new(bytes: &[u8]) -> Self {
+        Self::split(bytes, WORD_SIZE)
+    }
+
+    /// Take apart a certificate received as `chunks` of `chunk_size` bytes
+    /// each, `total_len` bytes in all.
+    ///
+    /// Fails if `chunk_size` is not a power of two of at most [WORD_SIZE].
+    pub fn from_parts(
+        total_len: usize,
+        chunk_size: usize,
+        chunks: Vec<[u8; WORD_SIZE]>,
+    ) -> Result<Self, CertificateError> {
+        check_chunk_size(chunk_size)?;
+        Ok(Self {
+            total_len,
+            chunk_size,
+            chunks,
+        })
+    }
+
+    /// Length in bytes of the certificate.
+    pub fn total_len(&self) -> usize {
+        self.total_len
+    }
+
+    /// Number of certificate bytes carried by each word.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// The padded words, in order.
+    pub fn chunks(&self) -> &[[u8; WORD_SIZE]] {
+        &self.chunks
+    }
+
+    fn split(bytes: &[u8], chunk_size: usize) -> Self {
+        let chunks = bytes
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let mut word = [0u8; WORD_SIZE];
+                word[..chunk.len()].copy_from_slice(chunk);
+                word
+            })
+            .collect();
+        Self {
+            total_len: bytes.len(),
+            chunk_size,
+            chunks,
+        }
+    }
+}
+
+/// Split `bytes` into zero-padded words that each carry `chunk_size` bytes.
+///
+/// `chunk_size` must be a power of two of at most [WORD_SIZE]; contracts store
+/// full words, so callers normally use [ChunkedCertificate::new], which picks
+/// [WORD_SIZE].
+pub fn chunk_certificate(
+    bytes: &[u8],
+    chunk_size: usize,
+) -> Result<ChunkedCertificate, CertificateError> {
+    check_chunk_size(chunk_size)?;
+    Ok(ChunkedCertificate::split(bytes, chunk_size))
+}
+
+/// Concatenate the bytes carried by the words of `chunked` and strip the
+/// padding, leaving the certificate of [ChunkedCertificate::total_len] bytes.
+///
+/// The length and the words may come from an untrusted relay, so nothing is
+/// allocated for a certificate of more than [MAX_CERTIFICATE_LEN] bytes, and
+/// the words must be exactly as many as the length takes up: a missing word
+/// would otherwise be mistaken for padding, and an extra one silently dropped.
+pub fn reassemble_certificate(chunked: &ChunkedCertificate) -> Result<Vec<u8>, CertificateError> {
+    if chunked.total_len > MAX_CERTIFICATE_LEN {
+        return Err(CertificateError::TooLong(chunked.total_len));
+    }
+    check_chunk_count(chunked.total_len, chunked.chunk_size, chunked.chunks.len())?;
+
+    let mut bytes: Vec<u8> = chunked
+        .chunks
+        .iter()
+        .flat_map(|word| &word[..chunked.chunk_size])
+        .copied()
+        .collect();
+    bytes.truncate(chunked.total_len);
+    Ok(bytes)
+}
+
+/// Number of words of `chunk_size` bytes each, as for [chunk_certificate],
+/// that a certificate of `size` bytes takes up, e.g. an
+/// [estimate_certificate_size](crate::estimate_certificate_size).
+///
+/// Fails if `chunk_size` is not a power of two of at most [WORD_SIZE].
+pub fn estimate_chunks(size: usize, chunk_size: usize) -> Result<usize, CertificateError> {
+    check_chunk_size(chunk_size)?;
+    Ok(chunk_count(size, chunk_size))
+}
+
+/// Collects the full words of a certificate chunked by
+/// [ChunkedCertificate::new] as they arrive, e.g. over several transactions,
+/// in any order.
+#[derive(Debug, Clone, Default)]
+pub struct CertificateAssembler {
+    chunks: BTreeMap<usize, [u8; WORD_SIZE]>,
+}
+
+impl CertificateAssembler {
+    /// Start with no chunks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the word at position `index` of [ChunkedCertificate::chunks].
+    ///
+    /// Delivering the same word twice is harmless, but a different word for
+    /// an index already seen is rejected and leaves the first one in place, as
+    /// is any word past [MAX_CERTIFICATE_LEN].
+    pub fn push_chunk(
+        &mut self,
+        index: usize,
+        chunk: [u8; WORD_SIZE],
+    ) -> Result<(), CertificateError> {
+        if index >= chunk_count(MAX_CERTIFICATE_LEN, WORD_SIZE) {
+            return Err(CertificateError::TooLong(
+                index.saturating_add(1).saturating_mul(WORD_SIZE),
+            ));
+        }
+        match self.chunks.get(&index) {
+            Some(seen) if *seen != chunk => Err(CertificateError::ConflictingChunk(index)),
+            Some(_) => Ok(()),
+            None => {
+                self.chunks.insert(index, chunk);
+                Ok(())
+            }
+        }
+    }
+
+    /// Indices of the words still needed for a certificate of `total_len`
+    /// bytes, which is rejected if it is more than [MAX_CERTIFICATE_LEN].
+    pub fn missing_chunks(&self, total_len: usize) -> Result<Vec<usize>, CertificateError> {
+        if total_len > MAX_CERTIFICATE_LEN {
+            return Err(CertificateError::TooLong(total_len));
+        }
+        Ok((0..chunk_count(total_len, WORD_SIZE))
+            .filter(|i| !self.chunks.contains_key(i))
+            .collect())
+    }
+
+    /// Reassemble and decode the certificate of `total_len` bytes.
+    ///
+    /// Returns `None` while chunks are missing, if chunks were pushed past the
+    /// end of the certificate, if `total_len` is more than
+    /// [MAX_CERTIFICATE_LEN], or if the bytes do not decode.
+    pub fn try_finalize(&self, total_len: usize) -> Option<StmAggrSig<H>> {
+        if !self.missing_chunks(total_len).ok()?.is_empty() {
+            return None;
+        }
+        check_chunk_count(total_len, WORD_SIZE, self.chunks.len()).ok()?;
+        let chunked = ChunkedCertificate {
+            total_len,
+            chunk_size: WORD_SIZE,
+            chunks: self.chunks.values().copied().collect(),
+        };
+        deserialize_certificate(&reassemble_certificate(&chunked).ok()?).ok()
+    }
+}
+
+/// Number of chunks of `chunk_size` bytes that hold `len` bytes.
+fn chunk_count(len: usize, chunk_size: usize) -> usize {
+    len / chunk_size + usize::from(len % chunk_size != 0)
+}
+
+/// Accept exactly as many chunks of `chunk_size` bytes as hold `len` bytes.
+fn check_chunk_count(len: usize, chunk_size: usize, actual: usize) -> Result<(), CertificateError> {
+    let expected = chunk_count(len, chunk_size);
+    if actual != expected {
+        return Err(CertificateError::ChunkCountMismatch { expected, actual });
+    }
+    Ok(())
+}
+
+/// Accept chunk sizes that are a power of two of at most [WORD_SIZE].
+fn check_chunk_size(chunk_size: usize) -> Result<(), CertificateError> {
+    if !chunk_size.is_power_of_two() || chunk_size > WORD_SIZE {
+        return Err(CertificateError::InvalidChunkSize(chunk_size));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compute_avk_for_stake, default_params, generate_aggregate_signatures,
+        serialize_certificate, verify_aggregate_signature,
+    };
+
+    const MSG: &[u8] = b"chunked certificate";
+
+    #[test]
+    fn chunked_certificate_round_trips_and_verifies() {
+        let params = default_params();
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        let avk = compute_avk_for_stake(vec![1; 4], params);
+        let bytes = serialize_certificate(&msig);
+
+        for chunk_size in [8, 16, WORD_SIZE] {
+            let chunked = chunk_certificate(&bytes, chunk_size).unwrap();
+            assert!(chunked.chunks().len() > 1);
+            assert_eq!(
+                chunked.chunks().len(),
+                estimate_chunks(bytes.len(), chunk_size).unwrap()
+            );
+            let last = chunked.chunks().last().unwrap();
+            let used = bytes.len() - (chunked.chunks().len() - 1) * chunk_size;
+            assert!(last[used..].iter().all(|&b| b == 0));
+
+            let reassembled = reassemble_certificate(&chunked).unwrap();
+            assert_eq!(reassembled, bytes);
+            let decoded = deserialize_certificate(&reassembled).unwrap();
+            verify_aggregate_signature(MSG, &decoded, &avk, params).unwrap();
+        }
+    }
+
+    #[test]
+    fn reassembly_rejects_a_wrong_number_of_chunks() {
+        let bytes: Vec<u8> = (0..100).collect();
+        let chunked = ChunkedCertificate::new(&bytes);
+        assert_eq!(chunked.chunks().len(), 4);
+
+        let mut missing = chunked.chunks().to_vec();
+        missing.pop();
+        let missing = ChunkedCertificate::from_parts(100, WORD_SIZE, missing).unwrap();
+        assert_eq!(
+            reassemble_certificate(&missing),
+            Err(CertificateError::ChunkCountMismatch {
+                expected: 4,
+                actual: 3
+            })
+        );
+
+        let mut extra = chunked.chunks().to_vec();
+        extra.push([0; WORD_SIZE]);
+        let extra = ChunkedCertificate::from_parts(100, WORD_SIZE, extra).unwrap();
+        assert_eq!(
+            reassemble_certificate(&extra),
+            Err(CertificateError::ChunkCountMismatch {
+                expected: 4,
+                actual: 5
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_chunk_sizes_are_rejected() {
+        for chunk_size in [0, 3, 24, 64] {
+            assert_eq!(
+                chunk_certificate(&[1, 2, 3], chunk_size),
+                Err(CertificateError::InvalidChunkSize(chunk_size))
+            );
+        }
+    }
+
+    #[test]
+    fn assembler_accepts_chunks_out_of_order() {
+        let params = default_params();
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        let bytes = serialize_certificate(&msig);
+        let chunked = ChunkedCertificate::new(&bytes);
+
+        let mut assembler = CertificateAssembler::new();
+        for (index, &chunk) in chunked.chunks().iter().enumerate().rev() {
+            assert!(assembler.try_finalize(bytes.len()).is_none());
+            assembler.push_chunk(index, chunk).unwrap();
+        }
+        assert!(assembler.missing_chunks(bytes.len()).unwrap().is_empty());
+        let decoded = assembler.try_finalize(bytes.len()).unwrap();
+        assert_eq!(serialize_certificate(&decoded), bytes);
+    }
+
+    #[test]
+    fn assembler_waits_for_a_missing_middle_chunk() {
+        let msig = generate_aggregate_signatures(MSG, default_params(), 4).unwrap();
+        let bytes = serialize_certificate(&msig);
+        let chunked = ChunkedCertificate::new(&bytes);
+        let middle = chunked.chunks().len() / 2;
+
+        let mut assembler = CertificateAssembler::new();
+        for (index, &chunk) in chunked.chunks().iter().enumerate() {
+            if index != middle {
+                assembler.push_chunk(index, chunk).unwrap();
+            }
+        }
+        assert_eq!(assembler.missing_chunks(bytes.len()).unwrap(), [middle]);
+        assert!(assembler.try_finalize(bytes.len()).is_none());
+    }
+
+    #[test]
+    fn assembler_rejects_a_conflicting_chunk() {
+        let mut assembler = CertificateAssembler::new();
+        assembler.push_chunk(3, [1; WORD_SIZE]).unwrap();
+        assembler.push_chunk(3, [1; WORD_SIZE]).unwrap();
+        assert_eq!(
+            assembler.push_chunk(3, [2; WORD_SIZE]),
+            Err(CertificateError::ConflictingChunk(3))
+        );
+    }
+
+    #[test]
+    fn every_chunk_size_rejects_a_wrong_number_of_chunks() {
+        let bytes: Vec<u8> = (0..100).collect();
+        for chunk_size in [8, 16, WORD_SIZE] {
+            let chunked = chunk_certificate(&bytes, chunk_size).unwrap();
+            let expected = chunked.chunks().len();
+
+            let mut extra = chunked.chunks().to_vec();
+            extra.push([0; WORD_SIZE]);
+            let extra = ChunkedCertificate::from_parts(100, chunk_size, extra).unwrap();
+            assert_eq!(
+                reassemble_certificate(&extra),
+                Err(CertificateError::ChunkCountMismatch {
+                    expected,
+                    actual: expected + 1
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn assembler_rejects_chunks_past_the_end() {
+        let msig = generate_aggregate_signatures(MSG, default_params(), 4).unwrap();
+        let bytes = serialize_certificate(&msig);
+        let chunked = ChunkedCertificate::new(&bytes);
+
+        let mut assembler = CertificateAssembler::new();
+        for (index, &chunk) in chunked.chunks().iter().enumerate() {
+            assembler.push_chunk(index, chunk).unwrap();
+        }
+        assert!(assembler.try_finalize(bytes.len()).is_some());
+
+        assembler
+            .push_chunk(chunked.chunks().len(), [0; WORD_SIZE])
+            .unwrap();
+        assert!(assembler.missing_chunks(bytes.len()).unwrap().is_empty());
+        assert!(assembler.try_finalize(bytes.len()).is_none());
+    }
+
+    #[test]
+    fn absurd_lengths_are_rejected_before_allocating() {
+        let absurd = ChunkedCertificate::from_parts(usize::MAX, WORD_SIZE, vec![]).unwrap();
+        assert_eq!(
+            reassemble_certificate(&absurd),
+            Err(CertificateError::TooLong(usize::MAX))
+        );
+
+        let mut assembler = CertificateAssembler::new();
+        assert_eq!(
+            assembler.missing_chunks(usize::MAX),
+            Err(CertificateError::TooLong(usize::MAX))
+        );
+        assert!(assembler.try_finalize(usize::MAX).is_none());
+        assert!(assembler.push_chunk(usize::MAX, [0; WORD_SIZE]).is_err());
+        assert!(assembler.missing_chunks(MAX_CERTIFICATE_LEN).is_ok());
+    }
+}
@@ -0,0 +1,1049 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::BTreeSet, panic, thread};
+
+use blake2::digest::{Digest, FixedOutput};
+use mithril_stm::{
+    key_reg::ClosedKeyReg,
+    stm::{
+        StmAggrSig, StmAggrVerificationKey, StmParameters, StmSig, StmSigRegParty,
+        StmVerificationKey,
+    },
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{debug, field, instrument, warn, Span};
+
+use crate::{
+    certificate::{decode_exact, decode_versioned, signatures},
+    domain_separated, message_digest, serialize_avk, try_compute_avk_for_reg, DefaultHasher,
+    MessageMode, RegistrationError, Stake, VerificationError, D, H,
+};
+
+/// Longest message, in bytes, that [verify_aggregate_signature] accepts.
+///
+/// Larger payloads should be signed and verified through their digest with
+/// [sign_digest](crate::sign_digest) and [verify_digest].
+pub const MAX_MESSAGE_LEN: usize = 1 << 20;
+
+/// Check that `msig` is a valid aggregate signature over `msg` for the
+/// committee committed to by `avk` under `params`.
+///
+/// `msg` must be the exact bytes that were signed. It is rejected before any
+/// hashing if it is empty or longer than [MAX_MESSAGE_LEN].
+/// The hasher `D` is inferred from the arguments and is usually
+/// [DefaultHasher](crate::DefaultHasher).
+///
+/// Verification is not constant time, and is not meant to be: every input is
+/// public, so how long it takes reveals nothing an observer could not compute
+/// from the inputs themselves. The cheap structural checks deliberately run
+/// first and stop at the first failure, so that malformed certificates are
+/// rejected without any pairing being computed.
+#[instrument(
+    name = "verify_certificate",
+    skip_all,
+    fields(k = params.k, m = params.m, phi_f = params.phi_f, signers = field::Empty)
+)]
+pub fn verify_aggregate_signature<D>(
+    msg: &[u8],
+    msig: &StmAggrSig<D>,
+    avk: &StmAggrVerificationKey<D>,
+    params: StmParameters,
+) -> Result<(), VerificationError>
+where
+    D: Clone + Digest + FixedOutput + Send + Sync,
+    StmAggrSig<D>: Serialize,
+{
+    let sigs = signatures(msig);
+    Span::current().record("signers", sigs.len());
+
+    let result = check_message(msg)
+        .and_then(|()| check_parameters(&sigs, &params))
+        .and_then(|()| check_quorum(&sigs, &params))
+        .and_then(|()| {
+            msig.verify(msg, avk, &params)
+                .map_err(|err| VerificationError::InvalidSignature {
+                    reason: err.to_string(),
+                })
+        });
+    // Every outcome, from an empty message to a bad signature, leaves through
+    // this single exit, so accepted and rejected certificates are reported
+    // alike.
+    match &result {
+        Ok(()) => debug!("certificate verified"),
+        Err(err) => warn!(?err, "certificate rejected"),
+    }
+    result
+}
+
+/// Check that `msig` is a valid aggregate signature over `digest`, as produced
+/// by [sign_digest](crate::sign_digest) for a message hashing to `digest`.
+///
+/// A certificate over a digest does not verify against the raw message, nor
+/// the other way round.
+pub fn verify_digest<D>(
+    digest: &[u8; 32],
+    msig: &StmAggrSig<D>,
+    avk: &StmAggrVerificationKey<D>,
+    params: StmParameters,
+) -> Result<(), VerificationError>
+where
+    D: Clone + Digest + FixedOutput + Send + Sync,
+    StmAggrSig<D>: Serialize,
+{
+    verify_aggregate_signature(digest, msig, avk, params)
+}
+
+/// Check that `msig` is a valid aggregate signature over `msg` bound to
+/// `domain`, as produced by [sign_with_domain](crate::sign_with_domain).
+///
+/// A certificate produced under one domain is rejected under any other.
+pub fn verify_with_domain<D>(
+    domain: &[u8],
+    msg: &[u8],
+    msig: &StmAggrSig<D>,
+    avk: &StmAggrVerificationKey<D>,
+    params: StmParameters,
+) -> Result<(), VerificationError>
+where
+    D: Clone + Digest + FixedOutput + Send + Sync,
+    StmAggrSig<D>: Serialize,
+{
+    verify_aggregate_signature(&domain_separated(domain, msg), msig, avk, params)
+}
+
+/// Check that `msig` is a valid aggregate signature over `msg` signed in
+/// `mode`, as produced by [sign_with_mode](crate::sign_with_mode).
+///
+/// Unlike [verify_digest], this takes the message itself in both modes. A
+/// certificate produced in one mode is rejected in the other.
+pub fn verify_with_mode<D>(
+    mode: MessageMode,
+    msg: &[u8],
+    msig: &StmAggrSig<D>,
+    avk: &StmAggrVerificationKey<D>,
+    params: StmParameters,
+) -> Result<(), VerificationError>
+where
+    D: Clone + Digest + FixedOutput + Send + Sync,
+    StmAggrSig<D>: Serialize,
+{
+    verify_aggregate_signature(&mode.signed_bytes(msg), msig, avk, params)
+}
+
+/// Like [verify_aggregate_signature], but run on the blocking thread pool of
+/// the current tokio runtime so that checking a certificate from a large
+/// committee does not stall other tasks.
+///
+/// The arguments are owned because the check outlives the caller's borrow. A
+/// panic during verification is resumed in the awaiting task.
+#[cfg(feature = "async")]
+pub async fn verify_aggregate_signature_async(
+    msg: Vec<u8>,
+    msig: StmAggrSig<H>,
+    avk: StmAggrVerificationKey<D>,
+    params: StmParameters,
+) -> Result<(), VerificationError> {
+    tokio::task::spawn_blocking(move || verify_aggregate_signature(&msg, &msig, &avk, params))
+        .await
+        .unwrap_or_else(|err| panic::resume_unwind(err.into_panic()))
+}
+
+/// Like [verify_aggregate_signature], but explain a failure caused by a
+/// certificate signed under a different registration than the one verified
+/// against.
+///
+/// `signing_avk` is the aggregate verification key the signers claim to have
+/// used, when known. If the certificate does not verify against `avk` and the
+/// two keys differ, e.g. because the stake distributions differ, the error is
+/// [VerificationError::RegistrationMismatch] with the fingerprints of both
+/// instead of a bare [VerificationError::InvalidSignature].
+pub fn verify_with_signing_avk(
+    msg: &[u8],
+    msig: &StmAggrSig<H>,
+    avk: &StmAggrVerificationKey<D>,
+    signing_avk: Option<&StmAggrVerificationKey<D>>,
+    params: StmParameters,
+) -> Result<(), VerificationError> {
+    match verify_aggregate_signature(msg, msig, avk, params) {
+        Err(VerificationError::InvalidSignature { reason }) => {
+            let expected = message_digest(&serialize_avk(avk));
+            match signing_avk.map(|avk| message_digest(&serialize_avk(avk))) {
+                Some(actual) if actual != expected => {
+                    Err(VerificationError::RegistrationMismatch { expected, actual })
+                }
+                _ => Err(VerificationError::InvalidSignature { reason }),
+            }
+        }
+        result => result,
+    }
+}
+
+/// Check a certificate given as
+/// [serialize_certificate](crate::serialize_certificate) bytes against an
+/// aggregate verification key given as [serialize_avk](crate::serialize_avk)
+/// bytes, as received by a verifier that never held the keys of the committee.
+///
+/// Bytes that do not decode are reported as
+/// [VerificationError::AvkDeserialization] for the key and
+/// [VerificationError::Deserialization] for the certificate.
+pub fn verify_with_avk_bytes(
+    msg: &[u8],
+    cert_bytes: &[u8],
+    avk_bytes: &[u8],
+    params: StmParameters,
+) -> Result<(), VerificationError> {
+    verify_bytes::<DefaultHasher>(msg, cert_bytes, avk_bytes, params)
+}
+
+/// Verifier of certificates from a single committee under fixed parameters,
+/// for a service that checks many certificates against a stable committee.
+///
+/// The aggregate verification key is derived once, when the verifier is
+/// built, rather than on every call as with
+/// [try_compute_avk_for_stake](crate::try_compute_avk_for_stake), which has to
+/// regenerate the key of every party.
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    avk: StmAggrVerificationKey<D>,
+    params: StmParameters,
+}
+
+impl Verifier {
+    /// A verifier for the committee of `reg` under `params`.
+    ///
+    /// Fails with [RegistrationError::Empty] if `reg` holds no party.
+    pub fn new(reg: &ClosedKeyReg<D>, params: StmParameters) -> Result<Self, RegistrationError> {
+        Ok(Self::from_avk(try_compute_avk_for_reg(reg)?, params))
+    }
+
+    /// A verifier for the committee committed to by `avk` under `params`.
+    pub fn from_avk(avk: StmAggrVerificationKey<D>, params: StmParameters) -> Self {
+        Self { avk, params }
+    }
+
+    /// The aggregate verification key certificates are checked against.
+    pub fn avk(&self) -> &StmAggrVerificationKey<D> {
+        &self.avk
+    }
+
+    /// Decode `cert_bytes`, as written by
+    /// [serialize_certificate](crate::serialize_certificate), and check the
+    /// certificate over `msg`. Bytes that do not decode are reported as
+    /// [VerificationError::Deserialization].
+    pub fn verify(&self, msg: &[u8], cert_bytes: &[u8]) -> Result<(), VerificationError> {
+        let msig: StmAggrSig<H> = decode_versioned(cert_bytes)?;
+        self.verify_certificate(msg, &msig)
+    }
+
+    /// Check an already decoded certificate over `msg`.
+    pub fn verify_certificate(
+        &self,
+        msg: &[u8],
+        msig: &StmAggrSig<H>,
+    ) -> Result<(), VerificationError> {
+        verify_aggregate_signature(msg, msig, &self.avk, self.params)
+    }
+}
+
+/// Check a certificate given as
+/// [serialize_certificate](crate::serialize_certificate) bytes over `msg`
+/// against the committee of `reg` and, if it verifies, list the verification
+/// keys of the parties that contributed to it, e.g. for auditing.
+///
+/// The keys are listed in the order of the signatures in the certificate, one
+/// per party. Fails with [VerificationError::EmptyCommittee] if `reg` holds no
+/// party.
+pub fn verify_and_list_signers(
+    msg: &[u8],
+    cert_bytes: &[u8],
+    reg: &ClosedKeyReg<D>,
+    params: StmParameters,
+) -> Result<Vec<StmVerificationKey>, VerificationError> {
+    let avk = try_compute_avk_for_reg(reg).map_err(|_| VerificationError::EmptyCommittee)?;
+    let msig: StmAggrSig<H> = decode_versioned(cert_bytes)?;
+    verify_aggregate_signature(msg, &msig, &avk, params)?;
+    // The batch proof of a verified certificate ties each signature to its
+    // party in the Merkle tree of `reg`.
+    Ok(signatures(&msig)
+        .into_iter()
+        .map(|sig_reg| sig_reg.reg_party.0)
+        .collect())
+}
+
+/// Parameter set a certificate verified under with [verify_with_fallback].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterSet {
+    /// The current parameters.
+    Primary,
+    /// The parameters being migrated away from.
+    Fallback,
+}
+
+/// Check a certificate given as
+/// [serialize_certificate](crate::serialize_certificate) bytes over `msg`
+/// under `primary_params` and, if it does not verify, under
+/// `fallback_params`, e.g. while certificates produced before a parameter
+/// upgrade are still in flight.
+///
+/// Returns the parameter set the certificate verified under. If it verifies
+/// under neither, the error is that of `primary_params`; bytes that do not
+/// decode are rejected without trying either.
+pub fn verify_with_fallback(
+    msg: &[u8],
+    cert_bytes: &[u8],
+    avk: &StmAggrVerificationKey<D>,
+    primary_params: StmParameters,
+    fallback_params: StmParameters,
+) -> Result<ParameterSet, VerificationError> {
+    let msig: StmAggrSig<H> = decode_versioned(cert_bytes)?;
+    match verify_aggregate_signature(msg, &msig, avk, primary_params) {
+        Ok(()) => Ok(ParameterSet::Primary),
+        Err(err) => {
+            debug!(?err, "retrying with the fallback parameters");
+            verify_aggregate_signature(msg, &msig, avk, fallback_params)
+                .map(|()| ParameterSet::Fallback)
+                .map_err(|_| err)
+        }
+    }
+}
+
+/// Digest a Mithril network hashes its key registration and Merkle tree with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashBackend {
+    /// Blake2b with a 256-bit output, the [DefaultHasher].
+    Blake2b256,
+    /// SHA-256.
+    #[cfg(feature = "sha2")]
+    Sha256,
+}
+
+/// Like [verify_with_avk_bytes], but for a certificate and key produced with
+/// the digest of `backend` rather than the [DefaultHasher].
+///
+/// A certificate does not verify under a backend other than its own.
+pub fn verify_any(
+    backend: HashBackend,
+    msg: &[u8],
+    cert_bytes: &[u8],
+    avk_bytes: &[u8],
+    params: StmParameters,
+) -> Result<(), VerificationError> {
+    match backend {
+        HashBackend::Blake2b256 => {
+            verify_bytes::<DefaultHasher>(msg, cert_bytes, avk_bytes, params)
+        }
+        #[cfg(feature = "sha2")]
+        HashBackend::Sha256 => verify_bytes::<sha2::Sha256>(msg, cert_bytes, avk_bytes, params),
+    }
+}
+
+/// Verify each `(msg, msig)` pair of `items` against the same `avk`, returning
+/// one result per item in order.
+///
+/// The whole batch is first checked at once with mithril-stm's batch
+/// verification. Only if that fails are the items verified one by one, so a
+/// bad certificate is reported without masking the others.
+pub fn batch_verify<D>(
+    items: &[(Vec<u8>, StmAggrSig<D>)],
+    avk: &StmAggrVerificationKey<D>,
+    params: StmParameters,
+) -> Vec<Result<(), VerificationError>>
+where
+    D: Clone + Digest + FixedOutput + Send + Sync,
+    StmAggrSig<D>: Serialize,
+{
+    let (msgs, msigs): (Vec<_>, Vec<_>) = items.iter().cloned().unzip();
+    let avks = vec![avk.clone(); items.len()];
+    let params_batch = vec![params; items.len()];
+
+    let batch_ok = !items.is_empty()
+        && msgs.iter().all(|msg| check_message(msg).is_ok())
+        && msigs
+            .iter()
+            .all(|msig| check_parameters(&signatures(msig), &params).is_ok())
+        && StmAggrSig::batch_verify(&msigs, &msgs, &avks, &params_batch).is_ok();
+    if batch_ok {
+        return vec![Ok(()); items.len()];
+    }
+
+    items
+        .iter()
+        .map(|(msg, msig)| verify_aggregate_signature(msg, msig, avk, params))
+        .collect()
+}
+
+/// Verify each `(msg, msig)` pair of `items` against the same `avk` on up to
+/// `workers` threads, returning one result per item in order.
+///
+/// The items are split into one contiguous run per worker, which all borrow
+/// `avk`. Unlike [batch_verify] every item is checked on its own, so a bad
+/// certificate costs no more than a good one.
+pub fn verify_stream<D>(
+    items: &[(Vec<u8>, StmAggrSig<D>)],
+    avk: &StmAggrVerificationKey<D>,
+    params: StmParameters,
+    workers: usize,
+) -> Vec<Result<(), VerificationError>>
+where
+    D: Clone + Digest + FixedOutput + Send + Sync,
+    StmAggrSig<D>: Serialize,
+{
+    if items.is_empty() {
+        return Vec::new();
+    }
+    let run = (items.len() + workers.max(1) - 1) / workers.max(1);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(run)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(msg, msig)| verify_aggregate_signature(msg, msig, avk, params))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|err| panic::resume_unwind(err))
+            })
+            .collect()
+    })
+}
+
+/// Whether `msig` holds at least `params.k` distinct lottery indices in
+/// `0..params.m`, i.e. enough wins for a quorum.
+///
+/// This only counts indices and checks no signature, so a certificate that
+/// meets the quorum may still be rejected by [verify_aggregate_signature].
+pub fn meets_quorum<D>(msig: &StmAggrSig<D>, params: &StmParameters) -> bool
+where
+    D: Clone + Digest + FixedOutput,
+    StmAggrSig<D>: Serialize,
+{
+    check_quorum(&signatures(msig), params).is_ok()
+}
+
+/// Check that `sig` is a valid individual signature over `msg` by the party
+/// registered with `pk` and `stake` in the committee committed to by `avk`.
+///
+/// This lets signatures be filtered before they are aggregated, so that a
+/// single bad signature does not make the whole aggregation fail.
+pub fn verify_single_sig<D: Clone + Digest + FixedOutput>(
+    sig: &StmSig,
+    msg: &[u8],
+    pk: &StmVerificationKey,
+    stake: Stake,
+    avk: &StmAggrVerificationKey<D>,
+    params: StmParameters,
+) -> Result<(), VerificationError> {
+    sig.verify(&params, pk, &stake, avk, msg)
+        .map_err(|err| VerificationError::InvalidSignature {
+            reason: err.to_string(),
+        })
+}
+
+/// Check that `sig` is a valid core signature over `msg` by the party holding
+/// `pk` and `stake`, and that it won the lottery indices it claims against
+/// `total_stake`, without any registration.
+///
+/// Core signatures are those of `StmSigner::core_sign`, made by signers set up
+/// with `StmInitializer::new_core_signer` before a registration is closed.
+/// Signatures for a certificate bind `msg` to the committee and are checked
+/// with [verify_single_sig] instead. Nothing here checks that the party
+/// belongs to a committee or that a quorum of `params.k` is reached, so this
+/// only pre-screens signatures from parties whose `pk` and `stake` are
+/// already trusted.
+pub fn core_verify_sig(
+    sig: &StmSig,
+    msg: &[u8],
+    params: StmParameters,
+    pk: &StmVerificationKey,
+    stake: Stake,
+    total_stake: Stake,
+) -> Result<(), VerificationError> {
+    sig.verify_core(&params, pk, &stake, msg, &total_stake)
+        .map_err(|err| VerificationError::InvalidSignature {
+            reason: err.to_string(),
+        })
+}
+
+/// Decode a certificate and a key hashed with `D` and verify the former
+/// against the latter.
+fn verify_bytes<D>(
+    msg: &[u8],
+    cert_bytes: &[u8],
+    avk_bytes: &[u8],
+    params: StmParameters,
+) -> Result<(), VerificationError>
+where
+    D: Clone + Digest + FixedOutput + Send + Sync,
+    StmAggrSig<D>: Serialize + DeserializeOwned,
+    StmAggrVerificationKey<D>: DeserializeOwned,
+{
+    let avk: StmAggrVerificationKey<D> =
+        decode_exact(avk_bytes).map_err(VerificationError::AvkDeserialization)?;
+    let msig: StmAggrSig<D> = decode_versioned(cert_bytes)?;
+    verify_aggregate_signature(msg, &msig, &avk, params)
+}
+
+/// Reject messages that are empty or longer than [MAX_MESSAGE_LEN].
+pub(crate) fn check_message(msg: &[u8]) -> Result<(), VerificationError> {
+    if msg.is_empty() {
+        return Err(VerificationError::EmptyMessage);
+    }
+    if msg.len() > MAX_MESSAGE_LEN {
+        return Err(VerificationError::MessageTooLong {
+            len: msg.len(),
+            max: MAX_MESSAGE_LEN,
+        });
+    }
+    Ok(())
+}
+
+/// Reject certificates whose `sigs` win fewer than `params.k` distinct lottery
+/// indices in `0..params.m`.
+fn check_quorum(sigs: &[StmSigRegParty], params: &StmParameters) -> Result<(), VerificationError> {
+    let indices: BTreeSet<u64> = sigs
+        .iter()
+        .flat_map(|sig_reg| sig_reg.sig.indexes.iter().copied())
+        .filter(|&i| i < params.m)
+        .collect();
+    let indices = indices.len() as u64;
+    if indices < params.k {
+        return Err(VerificationError::BelowQuorum {
+            indices,
+            k: params.k,
+        });
+    }
+    Ok(())
+}
+
+/// Reject certificates whose `sigs` hold lottery indices outside of
+/// `0..params.m`, which can only have been won under a different parameter set.
+fn check_parameters(
+    sigs: &[StmSigRegParty],
+    params: &StmParameters,
+) -> Result<(), VerificationError> {
+    for sig_reg in sigs {
+        if let Some(&index) = sig_reg.sig.indexes.iter().find(|&&i| i >= params.m) {
+            return Err(VerificationError::ParameterMismatch { index, m: params.m });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use mithril_stm::stm::{StmClerk, StmInitializer};
+    use rand_chacha::ChaCha20Rng;
+    use rand_core::SeedableRng;
+
+    use super::*;
+    use crate::{
+        aggregate_signatures, compute_avk_for_stake, default_params, deserialize_certificate,
+        find_signatures, find_signatures_with_indices, generate_aggregate_signatures,
+        generate_aggregate_signatures_for, generate_certificate_with_digest, serialize_certificate,
+        setup_committee, setup_equal_parties, sign_digest, sign_message, sign_with_domain,
+        sign_with_mode, AggregationError, DEFAULT_DOMAIN,
+    };
+
+    const MSG: &[u8] = b"verification tests";
+
+    #[test]
+    fn single_signature_over_another_message_is_rejected() {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let avk = try_compute_avk_for_reg(&reg).unwrap();
+
+        let mut sigs = find_signatures_with_indices(MSG, &ps, &[0, 1, 2, 3]);
+        assert!(sigs.len() > 1);
+        sigs[0].1 = sign_message(&ps[sigs[0].0], b"another message").unwrap();
+
+        for (i, (party, sig)) in sigs.iter().enumerate() {
+            let pk = ps[*party].verification_key();
+            let result = verify_single_sig(sig, MSG, &pk, 1, &avk, params);
+            assert_eq!(result.is_ok(), i != 0, "signature of party {party}");
+        }
+    }
+
+    #[test]
+    fn generic_verification_accepts_the_default_hasher() {
+        let params = default_params();
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        let avk: StmAggrVerificationKey<DefaultHasher> = compute_avk_for_stake(vec![1; 4], params);
+        verify_aggregate_signature::<DefaultHasher>(MSG, &msig, &avk, params).unwrap();
+    }
+
+    #[test]
+    fn batch_reports_each_invalid_item() {
+        let params = default_params();
+        let avk = compute_avk_for_stake(vec![1; 4], params);
+        let other = b"another message".to_vec();
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        let other_msig = generate_aggregate_signatures(&other, params, 4).unwrap();
+
+        let valid = vec![(MSG.to_vec(), msig.clone()), (other.clone(), other_msig)];
+        assert!(batch_verify(&valid, &avk, params).iter().all(Result::is_ok));
+
+        let mut mixed = valid;
+        mixed.insert(1, (other, msig));
+        let results = batch_verify(&mixed, &avk, params);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(VerificationError::InvalidSignature { .. })
+        ));
+        assert!(results[2].is_ok());
+    }
+
+    #[test]
+    fn digest_certificates_do_not_verify_the_raw_message() {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let avk = try_compute_avk_for_reg(&reg).unwrap();
+        let msg = vec![0xab; 4096];
+
+        let msig = sign_digest(&msg, &ps).unwrap();
+        verify_digest(&message_digest(&msg), &msig, &avk, params).unwrap();
+        assert!(verify_aggregate_signature(&msg, &msig, &avk, params).is_err());
+    }
+
+    #[test]
+    fn certificate_just_below_quorum_is_reported_as_such() {
+        let params = default_params();
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        let avk = compute_avk_for_stake(vec![1; 4], params);
+        let indices: BTreeSet<u64> = signatures(&msig)
+            .iter()
+            .flat_map(|sig_reg| sig_reg.sig.indexes.iter().copied())
+            .collect();
+        let indices = indices.len() as u64;
+
+        let exact = StmParameters {
+            k: indices,
+            ..params
+        };
+        assert!(meets_quorum(&msig, &exact));
+        let above = StmParameters {
+            k: indices + 1,
+            ..params
+        };
+        assert!(!meets_quorum(&msig, &above));
+        assert_eq!(
+            verify_aggregate_signature(MSG, &msig, &avk, above),
+            Err(VerificationError::BelowQuorum {
+                indices,
+                k: indices + 1
+            })
+        );
+    }
+
+    #[test]
+    fn corrupted_avk_bytes_are_reported_as_such() {
+        let params = default_params();
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        let avk = compute_avk_for_stake(vec![1; 4], params);
+        let cert_bytes = serialize_certificate(&msig);
+        let avk_bytes = serialize_avk(&avk);
+        assert_eq!(
+            verify_with_avk_bytes(MSG, &cert_bytes, &avk_bytes, params),
+            Ok(())
+        );
+
+        let truncated = &avk_bytes[..avk_bytes.len() - 1];
+        assert!(matches!(
+            verify_with_avk_bytes(MSG, &cert_bytes, truncated, params),
+            Err(VerificationError::AvkDeserialization(_))
+        ));
+        assert!(matches!(
+            verify_with_avk_bytes(MSG, &cert_bytes[..1], &avk_bytes, params),
+            Err(VerificationError::Deserialization(_))
+        ));
+    }
+
+    #[test]
+    fn blake2b_backend_verifies_its_own_certificates() {
+        let params = default_params();
+        let (cert, avk) =
+            generate_certificate_with_digest::<DefaultHasher>(MSG, params, vec![1; 4]).unwrap();
+        assert_eq!(
+            cert,
+            serialize_certificate(&generate_aggregate_signatures(MSG, params, 4).unwrap())
+        );
+        assert_eq!(
+            verify_any(HashBackend::Blake2b256, MSG, &cert, &avk, params),
+            Ok(())
+        );
+        assert!(verify_any(HashBackend::Blake2b256, b"other", &cert, &avk, params).is_err());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn sha256_backend_verifies_its_own_certificates_only() {
+        let params = default_params();
+        let (sha_cert, sha_avk) =
+            generate_certificate_with_digest::<sha2::Sha256>(MSG, params, vec![1; 4]).unwrap();
+        let (blake_cert, blake_avk) =
+            generate_certificate_with_digest::<DefaultHasher>(MSG, params, vec![1; 4]).unwrap();
+
+        assert_eq!(
+            verify_any(HashBackend::Sha256, MSG, &sha_cert, &sha_avk, params),
+            Ok(())
+        );
+        assert!(verify_any(HashBackend::Blake2b256, MSG, &sha_cert, &sha_avk, params).is_err());
+        assert!(verify_any(HashBackend::Sha256, MSG, &blake_cert, &blake_avk, params).is_err());
+    }
+
+    #[test]
+    fn core_signatures_pass_screening_but_not_the_quorum() {
+        let params = default_params();
+        let mut rng = ChaCha20Rng::from_seed([3; 32]);
+        let initializers: Vec<StmInitializer> = (0..4)
+            .map(|_| StmInitializer::setup(params, 1, &mut rng))
+            .collect();
+        let vks: Vec<StmVerificationKey> = initializers
+            .iter()
+            .map(|init| init.verification_key().vk)
+            .collect();
+
+        let leaves = vks
+            .iter()
+            .map(|vk| crate::registration::reg_party(*vk, 1))
+            .collect::<Vec<_>>();
+
+        let first = initializers.into_iter().next().unwrap();
+        let signer = first.new_core_signer::<D>(&leaves).unwrap();
+        let sig = signer.core_sign(MSG, 4).unwrap();
+        assert_eq!(core_verify_sig(&sig, MSG, params, &vks[0], 1, 4), Ok(()));
+        assert!(core_verify_sig(&sig, b"other", params, &vks[0], 1, 4).is_err());
+        assert!(core_verify_sig(&sig, MSG, params, &vks[1], 1, 4).is_err());
+
+        // A single party wins well short of the quorum, which aggregation
+        // still enforces.
+        assert!((sig.indexes.len() as u64) < params.k);
+        let ps = setup_equal_parties(params, 4);
+        let sigs = find_signatures(MSG, &ps, &[0]);
+        assert!(matches!(
+            aggregate_signatures(MSG, sigs, &StmClerk::from_signer(&ps[0])),
+            Err(AggregationError::NotEnoughSignatures { signatures: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn certificates_do_not_cross_domains() {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let avk = try_compute_avk_for_reg(&reg).unwrap();
+        let msig = sign_with_domain(DEFAULT_DOMAIN, MSG, &ps).unwrap();
+
+        assert_eq!(
+            verify_with_domain(DEFAULT_DOMAIN, MSG, &msig, &avk, params),
+            Ok(())
+        );
+        assert!(verify_with_domain(b"OTHER-PROTOCOL", MSG, &msig, &avk, params).is_err());
+        assert!(verify_aggregate_signature(MSG, &msig, &avk, params).is_err());
+        assert_ne!(domain_separated(b"ab", b"c"), domain_separated(b"a", b"bc"));
+    }
+
+    #[test]
+    fn stream_results_keep_the_input_order() {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let avk = try_compute_avk_for_reg(&reg).unwrap();
+        let msgs = vec![b"stream 0".to_vec(), b"stream 1".to_vec()];
+        let msigs: Vec<StmAggrSig<H>> = generate_aggregate_signatures_for(&msgs, &ps)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+
+        // Every third item pairs a message with the other message's
+        // certificate.
+        let items: Vec<(Vec<u8>, StmAggrSig<H>)> = (0..100)
+            .map(|i| {
+                let cert = if i % 3 == 0 { 1 - i % 2 } else { i % 2 };
+                (msgs[i % 2].clone(), msigs[cert].clone())
+            })
+            .collect();
+
+        let results = verify_stream(&items, &avk, params, 4);
+        assert_eq!(results.len(), items.len());
+        for (i, result) in results.iter().enumerate() {
+            assert_eq!(result.is_ok(), i % 3 != 0, "item {i}");
+        }
+        assert!(verify_stream(&items[..0], &avk, params, 4).is_empty());
+    }
+
+    #[test]
+    fn messages_are_checked_before_anything_else() {
+        let params = default_params();
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        let avk = compute_avk_for_stake(vec![1; 4], params);
+
+        assert_eq!(
+            verify_aggregate_signature(b"", &msig, &avk, params),
+            Err(VerificationError::EmptyMessage)
+        );
+        assert_eq!(
+            verify_aggregate_signature(&vec![0; MAX_MESSAGE_LEN + 1], &msig, &avk, params),
+            Err(VerificationError::MessageTooLong {
+                len: MAX_MESSAGE_LEN + 1,
+                max: MAX_MESSAGE_LEN
+            })
+        );
+        // A message of exactly the limit gets past the check and is only
+        // rejected because the certificate is over another message.
+        assert!(matches!(
+            verify_aggregate_signature(&vec![0; MAX_MESSAGE_LEN], &msig, &avk, params),
+            Err(VerificationError::InvalidSignature { .. })
+        ));
+    }
+
+    #[test]
+    fn signing_under_another_stake_is_reported_as_a_mismatch() {
+        let params = default_params();
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        let signing = compute_avk_for_stake(vec![1; 4], params);
+        let verifying = compute_avk_for_stake(vec![1, 1, 2, 1], params);
+
+        assert_eq!(
+            verify_with_signing_avk(MSG, &msig, &verifying, Some(&signing), params),
+            Err(VerificationError::RegistrationMismatch {
+                expected: message_digest(&serialize_avk(&verifying)),
+                actual: message_digest(&serialize_avk(&signing)),
+            })
+        );
+        assert!(matches!(
+            verify_with_signing_avk(MSG, &msig, &verifying, None, params),
+            Err(VerificationError::InvalidSignature { .. })
+        ));
+        assert_eq!(
+            verify_with_signing_avk(MSG, &msig, &signing, Some(&signing), params),
+            Ok(())
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_verification_matches_the_sync_result() {
+        let params = default_params();
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        let avk = compute_avk_for_stake(vec![1; 4], params);
+
+        for msg in [MSG, &b"other message"[..]] {
+            let sync = verify_aggregate_signature(msg, &msig, &avk, params);
+            let result =
+                verify_aggregate_signature_async(msg.to_vec(), msig.clone(), avk.clone(), params)
+                    .await;
+            assert_eq!(result, sync);
+        }
+    }
+
+    /// Collects the message of every event, to see which exit a verification
+    /// left through.
+    #[derive(Default)]
+    struct Exits(std::sync::Mutex<Vec<String>>);
+
+    impl tracing::Subscriber for Exits {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
+        }
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+        fn event(&self, event: &tracing::Event<'_>) {
+            struct Message(String);
+            impl tracing::field::Visit for Message {
+                fn record_debug(&mut self, field: &field::Field, value: &dyn std::fmt::Debug) {
+                    if field.name() == "message" {
+                        self.0 = format!("{value:?}");
+                    }
+                }
+            }
+            let mut message = Message(String::new());
+            event.record(&mut message);
+            self.0.lock().unwrap().push(message.0);
+        }
+        fn enter(&self, _: &tracing::span::Id) {}
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[test]
+    fn every_outcome_leaves_through_the_same_exit() {
+        let params = default_params();
+        let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+        let avk = compute_avk_for_stake(vec![1; 4], params);
+        let exits = |msg: &[u8], params| {
+            let exits = std::sync::Arc::new(Exits::default());
+            let result = tracing::subscriber::with_default(exits.clone(), || {
+                verify_aggregate_signature(msg, &msig, &avk, params)
+            });
+            let messages = exits.0.lock().unwrap().clone();
+            (result, messages)
+        };
+
+        let (result, messages) = exits(MSG, params);
+        assert_eq!(result, Ok(()));
+        assert_eq!(messages, ["certificate verified"]);
+
+        let below_quorum = StmParameters {
+            k: params.m,
+            ..params
+        };
+        let other_m = StmParameters { m: 10, ..params };
+        for (msg, params) in [
+            (&b""[..], params),
+            (MSG, other_m),
+            (MSG, below_quorum),
+            (&b"other message"[..], params),
+        ] {
+            let (result, messages) = exits(msg, params);
+            assert!(result.is_err());
+            assert_eq!(messages, ["certificate rejected"], "{result:?}");
+        }
+    }
+
+    #[test]
+    fn certificates_verify_only_in_the_mode_they_were_signed_in() {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let avk = try_compute_avk_for_reg(&reg).unwrap();
+
+        let raw = sign_with_mode(MessageMode::Raw, MSG, &ps).unwrap();
+        let digest = sign_with_mode(MessageMode::Digest, MSG, &ps).unwrap();
+
+        assert_eq!(
+            verify_with_mode(MessageMode::Raw, MSG, &raw, &avk, params),
+            Ok(())
+        );
+        assert_eq!(
+            verify_with_mode(MessageMode::Digest, MSG, &digest, &avk, params),
+            Ok(())
+        );
+        assert!(matches!(
+            verify_with_mode(MessageMode::Digest, MSG, &raw, &avk, params),
+            Err(VerificationError::InvalidSignature { .. })
+        ));
+        assert!(matches!(
+            verify_with_mode(MessageMode::Raw, MSG, &digest, &avk, params),
+            Err(VerificationError::InvalidSignature { .. })
+        ));
+    }
+
+    #[test]
+    fn verifier_checks_many_certificates_against_one_committee() {
+        let params = default_params();
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let verifier = Verifier::new(&reg, params).unwrap();
+        assert_eq!(
+            serialize_avk(verifier.avk()),
+            serialize_avk(&try_compute_avk_for_reg(&reg).unwrap())
+        );
+
+        for msg in [&b"first"[..], &b"second"[..], &b"third"[..]] {
+            let cert = serialize_certificate(&sign_with_mode(MessageMode::Raw, msg, &ps).unwrap());
+            for _ in 0..2 {
+                assert_eq!(verifier.verify(msg, &cert), Ok(()));
+            }
+            assert!(matches!(
+                verifier.verify(b"another message", &cert),
+                Err(VerificationError::InvalidSignature { .. })
+            ));
+            assert!(matches!(
+                verifier.verify(msg, &cert[..cert.len() - 1]),
+                Err(VerificationError::Deserialization(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn listed_signers_are_the_lottery_winners() {
+        // The lottery does not depend on `k`, so setting it to the number of
+        // indices won by the whole committee keeps every winner.
+        let probe = StmParameters {
+            k: 1,
+            ..default_params()
+        };
+        let (ps, _) = setup_committee(probe, vec![1; 4]);
+        let winners = find_signatures(MSG, &ps, &[0, 1, 2, 3]);
+        let won: BTreeSet<u64> = winners
+            .iter()
+            .flat_map(|sig| sig.indexes.iter().copied())
+            .collect();
+        let params = StmParameters {
+            k: won.len() as u64,
+            ..probe
+        };
+
+        let (ps, reg) = setup_committee(params, vec![1; 4]);
+        let sigs = find_signatures(MSG, &ps, &[0, 1, 2, 3]);
+        let msig = aggregate_signatures(MSG, sigs, &StmClerk::from_signer(&ps[0])).unwrap();
+        let cert = serialize_certificate(&msig);
+
+        let listed = verify_and_list_signers(MSG, &cert, &reg, params).unwrap();
+        assert_eq!(listed.len(), winners.len());
+        for sig in &winners {
+            let vk = reg.reg_parties[sig.signer_index as usize].0;
+            assert!(listed.contains(&vk));
+        }
+        assert!(matches!(
+            verify_and_list_signers(b"another message", &cert, &reg, params),
+            Err(VerificationError::InvalidSignature { .. })
+        ));
+    }
+
+    #[test]
+    fn certificates_of_either_parameter_set_verify_during_a_migration() {
+        let fallback = default_params();
+        let primary = StmParameters {
+            k: 400,
+            m: 3000,
+            ..fallback
+        };
+        let avk = compute_avk_for_stake(vec![1; 4], fallback);
+        let cert =
+            |params| serialize_certificate(&generate_aggregate_signatures(MSG, params, 4).unwrap());
+        let old = cert(fallback);
+        let new = cert(primary);
+
+        assert_eq!(
+            verify_with_fallback(MSG, &new, &avk, primary, fallback),
+            Ok(ParameterSet::Primary)
+        );
+        assert_eq!(
+            verify_with_fallback(MSG, &old, &avk, primary, fallback),
+            Ok(ParameterSet::Fallback)
+        );
+
+        // Failing both reports the error under the primary parameters.
+        let other = b"another message";
+        let decoded = deserialize_certificate(&old).unwrap();
+        assert_eq!(
+            verify_with_fallback(other, &old, &avk, primary, fallback),
+            Err(verify_aggregate_signature(other, &decoded, &avk, primary).unwrap_err())
+        );
+        assert!(matches!(
+            verify_with_fallback(MSG, &old[..old.len() - 1], &avk, primary, fallback),
+            Err(VerificationError::Deserialization(_))
+        ));
+    }
+}
@@ -0,0 +1,36 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! JavaScript bindings of certificate verification, e.g. for a browser-based
+//! explorer.
+//!
+//! Verification draws no randomness and only hashes with Blake2b, so it runs
+//! on `wasm32-unknown-unknown` as is.
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{default_params, verify_with_avk_bytes};
+
+/// Check a certificate given as
+/// [serialize_certificate](crate::serialize_certificate) bytes over `msg`
+/// against an aggregate verification key given as
+/// [serialize_avk](crate::serialize_avk) bytes, under the
+/// [default_params].
+///
+/// JavaScript only gets the verdict; bytes that do not decode are reported as
+/// `false` like any other rejection.
+#[wasm_bindgen(js_name = verifyCertificate)]
+pub fn verify_certificate_wasm(msg: &[u8], cert_bytes: &[u8], avk_bytes: &[u8]) -> bool {
+    verify_with_avk_bytes(msg, cert_bytes, avk_bytes, default_params()).is_ok()
+}
@@ -0,0 +1,186 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! End-to-end tests of the `bonsai` command line tool.
+
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{self, Command, Output, Stdio},
+};
+
+/// Run `bonsai` with `args`.
+#[allow(clippy::unwrap_used)]
+fn bonsai(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bonsai"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// A file path in the temporary directory that no other test uses.
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("bonsai-cli-{}-{name}", process::id()))
+}
+
+#[allow(clippy::unwrap_used)]
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn generated_certificate_verifies_only_over_its_message() {
+    let registration = temp_path("subcommands.reg");
+    let registration = registration.to_str().unwrap();
+
+    let generated = bonsai(&[
+        "generate",
+        "--message",
+        "hello",
+        "--registration-out",
+        registration,
+    ]);
+    assert!(generated.status.success());
+    let certificate = stdout(&generated);
+    let certificate = certificate.trim();
+    assert!(certificate.starts_with("0x"));
+
+    let verify = |message| {
+        bonsai(&[
+            "verify",
+            "--message",
+            message,
+            "--certificate",
+            certificate,
+            "--registration",
+            registration,
+        ])
+        .status
+        .code()
+    };
+    let valid = verify("hello");
+    let invalid = verify("goodbye");
+    fs::remove_file(registration).unwrap();
+
+    assert_eq!(valid, Some(0));
+    assert_eq!(invalid, Some(1));
+}
+
+#[test]
+fn params_prints_the_parameter_set() {
+    let output = bonsai(&["--k", "5", "params"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "k = 5\nm = 2642\nphi_f = 0.2\n");
+}
+
+#[test]
+fn failures_exit_non_zero_and_report_to_stderr() {
+    let registration = temp_path("failures.reg");
+    let registration = registration.to_str().unwrap();
+
+    let generated = bonsai(&[
+        "generate",
+        "--message",
+        "hello",
+        "--registration-out",
+        registration,
+    ]);
+    assert!(generated.status.success());
+    let certificate = stdout(&generated);
+    let certificate = certificate.trim();
+
+    // Raise the stake claimed by the first signature, which still decodes but
+    // no longer matches the registration. It follows the version byte, the
+    // signature count, the 48 bytes of sigma, the indices with their length,
+    // the signer index and the 96 bytes of the verification key.
+    let mut bytes = hex::decode(certificate.trim_start_matches("0x")).unwrap();
+    let indices = u64::from_le_bytes(bytes[57..65].try_into().unwrap()) as usize;
+    bytes[65 + 8 * indices + 8 + 96] ^= 1;
+    let tampered = format!("0x{}", hex::encode(bytes));
+
+    let verify = |certificate: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_bonsai"))
+            .args(["verify", "--message", "hello", "--certificate", certificate])
+            .args(["--registration", registration])
+            .env("RUST_LOG", "off")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        (output.status.code(), output.stdout.is_empty(), stderr)
+    };
+    let invalid = verify(&tampered);
+    let malformed = verify("0x00");
+    fs::remove_file(registration).unwrap();
+
+    let (code, quiet, stderr) = invalid;
+    assert!(quiet);
+    assert_eq!(code, Some(1));
+    assert!(stderr.starts_with("verification failed: "), "{stderr}");
+
+    let (code, quiet, stderr) = malformed;
+    assert!(quiet);
+    assert_eq!(code, Some(2));
+    assert!(stderr.starts_with("invalid certificate: "), "{stderr}");
+}
+
+#[test]
+fn every_generate_format_verifies() {
+    let registration = temp_path("formats.reg");
+    let registration = registration.to_str().unwrap();
+
+    for format in ["raw", "hex", "base64", "json"] {
+        let generated = bonsai(&[
+            "generate",
+            "--message",
+            "hello",
+            "--format",
+            format,
+            "--registration-out",
+            registration,
+        ]);
+        assert!(generated.status.success(), "{format}");
+
+        let mut verify = Command::new(env!("CARGO_BIN_EXE_bonsai"))
+            .args(["verify", "--message", "hello", "--certificate", "-"])
+            .args(["--format", format, "--registration", registration])
+            .env("RUST_LOG", "off")
+            .stdin(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = verify.stdin.take().unwrap();
+        stdin.write_all(&generated.stdout).unwrap();
+        drop(stdin);
+        let code = verify.wait().unwrap().code();
+        fs::remove_file(registration).unwrap();
+        assert_eq!(code, Some(0), "{format}");
+    }
+}
+
+#[test]
+fn selftest_passes_with_the_defaults_and_fails_without_a_quorum() {
+    let output = bonsai(&["selftest"]);
+    assert!(output.status.success());
+    let report = stdout(&output);
+    for step in ["generate: ", "serialize and deserialize: ", "verify: "] {
+        assert!(report.contains(step), "{report}");
+    }
+    assert!(report.ends_with("selftest passed\n"), "{report}");
+
+    // Four parties expect about 26 of the 357 indices the quorum needs.
+    let output = bonsai(&["--phi-f", "0.01", "selftest"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("selftest failed to generate: "), "{stderr}");
+}
@@ -0,0 +1,43 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of the JavaScript bindings in a headless browser, run with
+//! `wasm-pack test --headless --firefox --no-default-features --features wasm`.
+
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use bonsai_experiment::{
+    compute_avk_for_stake, default_params, generate_aggregate_signatures, serialize_avk,
+    serialize_certificate, verify_certificate_wasm,
+};
+use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+const MSG: &[u8] = b"wasm verification";
+
+#[wasm_bindgen_test]
+fn known_good_certificate_verifies_in_the_browser() {
+    // The default seed makes the committee and its certificate the same on
+    // every run.
+    let params = default_params();
+    let msig = generate_aggregate_signatures(MSG, params, 4).unwrap();
+    let cert = serialize_certificate(&msig);
+    let avk = serialize_avk(&compute_avk_for_stake(vec![1; 4], params));
+
+    assert!(verify_certificate_wasm(MSG, &cert, &avk));
+    assert!(!verify_certificate_wasm(b"another message", &cert, &avk));
+    assert!(!verify_certificate_wasm(MSG, &cert[..cert.len() - 1], &avk));
+    assert!(!verify_certificate_wasm(MSG, &cert, &avk[1..]));
+}